@@ -1,38 +1,152 @@
 extern crate regex;
 extern crate itertools;
 
+use std::env;
+use std::fmt;
+use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
 use regex::Regex;
 use std::ops::Index;
 use itertools::Itertools;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+// a light's starting position and its velocity per second
+type Light = ((i32,i32), (i32,i32));
+
+// usage: day10 [max seconds to search]
+//        day10 frames [output dir] [seconds either side] [max frame size]
+//        day10 scan [fraction of points with a neighbour] [max connected groups]
 fn main() {
-    pt1();
+    let args : Vec<String> = env::args().skip(1).collect();
+    if args.first().map(|a| a.as_str()) == Some("scan") {
+        let criteria = Alignment {
+            neighboured : args.get(1).map_or(1.0, |f| f.parse().expect("fraction should be a number")),
+            max_components : args.get(2).map(|c| c.parse().expect("groups should be a number"))
+        };
+        match scan(&parse_file("input"), &criteria, 100_000) {
+            Some(t) => println!("Aligned after {} steps", t),
+            None => println!("Not aligned within 100000 steps")
+        }
+        return;
+    }
+    if args.first().map(|a| a.as_str()) == Some("frames") {
+        let dir = args.get(1).map_or("frames", |a| a.as_str());
+        let radius = args.get(2).map_or(5, |r| r.parse().expect("seconds should be a number"));
+        let size = args.get(3).map_or(200, |s| s.parse().expect("frame size should be a number"));
+        write_frames(&parse_file("input"), dir, radius, size);
+        return;
+    }
+    let max_time = args.first().map(|m| m.parse().expect("max seconds should be a number")).unwrap_or(100_000);
+    pt1(max_time);
+}
+
+// writes a PBM per second around convergence, or around the time limit if the lights never settle
+fn write_frames(input : &[Light], dir : &str, radius : i32, size : usize) {
+    let centre = match converge(input, 100_000) {
+        Ok(t) => t,
+        Err(ConvergeError::TimeLimit(t)) => t,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    fs::create_dir_all(dir).expect("unable to create frame directory");
+    for t in (centre - radius).max(0)..=centre + radius {
+        let filename = format!("{}/frame_{:06}.pbm", dir, t);
+        let mut f = File::create(&filename).expect("unable to create frame");
+        f.write_all(&to_pbm(&step(input, t), size)).unwrap();
+    }
+    println!("Wrote seconds {} to {} into {}", (centre - radius).max(0), centre + radius, dir);
 }
 
-fn pt1() {
+fn pt1(max_time : i32) {
     let input = parse_file("input");
-    let mut steps = 0;
-    loop {
-        let curr = step(&input, steps);
-        let s = split(&curr);
-        if aligned(&s) {
-            println!("Aligned after {} steps!", steps);
+    match converge(&input, max_time) {
+        Ok(steps) => {
+            let s = split(&step(&input, steps));
+            if Sky::at(&input, steps).is_aligned(&Alignment::default()) {
+                println!("Aligned after {} steps!", steps);
+            } else {
+                println!("Smallest after {} steps, but not every point has a neighbour", steps);
+            }
             display(&s);
-            return;
-        } 
-        steps = steps + 1;
+            match recognise(&step(&input, steps), &Font::standard()) {
+                Ok(message) => println!("Message: {}", message),
+                Err(e) => println!("{}", e)
+            }
+        },
+        Err(e) => println!("{}", e)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ConvergeError {
+    NoLights,
+    // the bounding box was still shrinking at the time limit
+    TimeLimit(i32)
+}
+
+impl fmt::Display for ConvergeError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConvergeError::NoLights => write!(f, "no lights to converge"),
+            ConvergeError::TimeLimit(t) => write!(f, "lights still converging after {} seconds", t)
+        }
     }
 }
 
-fn parse_file(filename : &str) -> Vec<((i32,i32), (i32,i32))> {
+// width plus height of the bounding box at time t. Each edge is a max (or min) of lines in t, so
+// this is convex and a ternary search finds its minimum; the area itself needn't be unimodal.
+fn extent(state : &[Light], t : i64) -> i64 {
+    let (width, height) = bounding_size(state, t);
+    width + height
+}
+
+fn bounding_area(state : &[Light], t : i64) -> i64 {
+    let (width, height) = bounding_size(state, t);
+    (width + 1) * (height + 1)
+}
+
+// widened to i64 as far-off times overflow the i32 positions that step uses
+fn bounding_size(state : &[Light], t : i64) -> (i64, i64) {
+    let xs = state.iter().map(|((x,_),(dx,_))| *x as i64 + *dx as i64 * t);
+    let ys = state.iter().map(|((_,y),(_,dy))| *y as i64 + *dy as i64 * t);
+    let (min_x, max_x) = xs.minmax().into_option().unwrap();
+    let (min_y, max_y) = ys.minmax().into_option().unwrap();
+    (max_x - min_x, max_y - min_y)
+}
+
+// the earliest second in 0..=max_time at which the lights are packed most tightly
+fn converge(state : &[Light], max_time : i32) -> Result<i32, ConvergeError> {
+    if state.is_empty() {
+        return Err(ConvergeError::NoLights);
+    }
+    let (mut lo, mut hi) = (0i64, max_time.max(0) as i64);
+    while hi - lo > 2 {
+        let m1 = lo + (hi - lo) / 3;
+        let m2 = hi - (hi - lo) / 3;
+        if extent(state, m1) <= extent(state, m2) {
+            hi = m2;
+        } else {
+            lo = m1;
+        }
+    }
+    // ties on the extent are broken by the area
+    let best = (lo..=hi).min_by_key(|t| (extent(state, *t), bounding_area(state, *t), *t)).unwrap();
+    // a minimum on the limit only counts if the lights start spreading out again afterwards
+    if best == hi && extent(state, best + 1) < extent(state, best) {
+        return Err(ConvergeError::TimeLimit(max_time));
+    }
+    Ok(best as i32)
+}
+
+fn parse_file(filename : &str) -> Vec<Light> {
     let r= Regex::new(r"position=<\s*(\-?\d+),\s*(\-?\d+)> velocity=<\s*(\-?\d+),\s*(\-?\d+)>").unwrap();
-    let mut entries : Vec<((i32,i32), (i32,i32))> = vec![];
+    let mut entries : Vec<Light> = vec![];
     let mut f = File::open(filename).expect("file not found");
     let mut contents = String::new();
-    f.read_to_string(&mut contents);
+    f.read_to_string(&mut contents).expect("failed to read input");
     contents.trim().lines().for_each(|s| {
         let c = r.captures(s).unwrap();
         entries.push( ((c.index(1).parse::<i32>().unwrap(), c.index(2).parse::<i32>().unwrap()),
@@ -41,13 +155,13 @@ fn parse_file(filename : &str) -> Vec<((i32,i32), (i32,i32))> {
     entries
 }
 
-fn step(state : &Vec<((i32,i32), (i32,i32))>, step : i32) -> Vec<(i32,i32)> {
+fn step(state : &[Light], step : i32) -> Vec<(i32,i32)> {
     state.iter().map(|((x,y),(dx,dy))| (x+(step*(*dx)), y + (step*(*dy)))).collect()
 }
 
-fn split(state : &Vec<(i32,i32)>) -> Vec<(i32, Vec<i32>)> {
-    let mut s = state.clone();
-    s.sort_by(|a,b| a.1.cmp(&(b.1)));
+fn split(state : &[(i32,i32)]) -> Vec<(i32, Vec<i32>)> {
+    let mut s = state.to_vec();
+    s.sort_by_key(|a| a.1);
     let mut grid : Vec<(i32, Vec<i32>)> = vec![];
     for (y, group) in s.iter().group_by(|(_,y)| *y).into_iter() {
         let mut xs : Vec<i32> = group.into_iter().map(|(x,_)| *x).collect();
@@ -58,11 +172,11 @@ fn split(state : &Vec<(i32,i32)>) -> Vec<(i32, Vec<i32>)> {
     grid
 }
 
-fn display( state : &Vec<(i32, Vec<i32>)> ) {
-    let min_x= state.iter().map(|(_, xs)| xs.iter().min().unwrap()).into_iter().min().unwrap();
-    let max_x = state.iter().map(|(_, xs)| xs.iter().max().unwrap()).into_iter().max().unwrap();
-    let min_y = state.iter().map(|(y, _)| y).into_iter().min().unwrap();
-    let max_y = state.iter().map(|(y, _)| y).into_iter().max().unwrap();
+fn display( state : &[(i32, Vec<i32>)] ) {
+    let min_x= state.iter().map(|(_, xs)| xs.iter().min().unwrap()).min().unwrap();
+    let max_x = state.iter().map(|(_, xs)| xs.iter().max().unwrap()).max().unwrap();
+    let min_y = state.iter().map(|(y, _)| y).min().unwrap();
+    let max_y = state.iter().map(|(y, _)| y).max().unwrap();
     let empty_vec = Vec::<i32>::new();
     let mut x_iter = empty_vec.iter();
     let mut result = String::new();
@@ -99,27 +213,257 @@ fn display( state : &Vec<(i32, Vec<i32>)> ) {
     println!("{}", result);    
 }
 
-fn aligned(state : &Vec<(i32,Vec<i32>)>) -> bool {
-    let mut rows = HashMap::<i32, &Vec<i32>>::new();
-    // aligned if every entry has an adjoined neighbour
-    for (y, xs) in state.iter() {
-        rows.insert(*y, &xs);
-    }
-    for (y, xs) in state.iter() {    
-        for x in xs.iter() {
-            if !(xs.contains(&(x+1)) || 
-                 xs.contains(&(x-1)) || 
-                 rows.get(&(y-1)).unwrap_or(&&vec![]).contains(&(x-1)) ||
-                 rows.get(&(y-1)).unwrap_or(&&vec![]).contains(x) ||
-                 rows.get(&(y-1)).unwrap_or(&&vec![]).contains(&(x+1)) ||
-                 rows.get(&(y+1)).unwrap_or(&&vec![]).contains(&(x-1)) ||
-                 rows.get(&(y+1)).unwrap_or(&&vec![]).contains(x) ||
-                 rows.get(&(y+1)).unwrap_or(&&vec![]).contains(&(x+1))) {
-                return false;
+// the lights' positions at a given second, hashed for constant time neighbour lookups
+struct Sky {
+    velocities : Vec<(i32,i32)>,
+    positions : Vec<(i32,i32)>,
+    // how many lights are on each lit position, as lights can overlap
+    lit : HashMap<(i32,i32), usize>,
+    time : i32
+}
+
+const NEIGHBOURS : [(i32,i32); 8] = [(-1,-1), (0,-1), (1,-1), (-1,0), (1,0), (-1,1), (0,1), (1,1)];
+
+// what it takes for the lights to count as aligned
+struct Alignment {
+    // the fraction of lights that must have a lit neighbour
+    neighboured : f64,
+    max_components : Option<usize>
+}
+
+impl Default for Alignment {
+    // every light has a neighbour, however many letters they make up
+    fn default() -> Alignment {
+        Alignment { neighboured : 1.0, max_components : None }
+    }
+}
+
+impl Sky {
+    fn at(lights : &[Light], time : i32) -> Sky {
+        let positions = step(lights, time);
+        let mut lit = HashMap::new();
+        for p in positions.iter() {
+            *lit.entry(*p).or_insert(0) += 1;
+        }
+        Sky { velocities : lights.iter().map(|(_, v)| *v).collect(), positions, lit, time }
+    }
+
+    // moves every light on by a second, updating the index in place
+    fn advance(&mut self) {
+        for (p, (dx, dy)) in self.positions.iter_mut().zip(self.velocities.iter()) {
+            if let Some(count) = self.lit.get_mut(p) {
+                *count -= 1;
+                if *count == 0 {
+                    self.lit.remove(p);
+                }
+            }
+            *p = (p.0 + dx, p.1 + dy);
+            *self.lit.entry(*p).or_insert(0) += 1;
+        }
+        self.time += 1;
+    }
+
+    fn has_neighbour(&self, (x, y) : (i32,i32)) -> bool {
+        NEIGHBOURS.iter().any(|(dx, dy)| self.lit.contains_key(&(x + dx, y + dy)))
+    }
+
+    fn neighboured_fraction(&self) -> f64 {
+        if self.positions.is_empty() {
+            return 1.0;
+        }
+        let count = self.positions.iter().filter(|p| self.has_neighbour(**p)).count();
+        count as f64 / self.positions.len() as f64
+    }
+
+    // groups of lit positions that touch, diagonals included
+    fn components(&self) -> usize {
+        let mut seen = HashSet::new();
+        let mut components = 0;
+        for start in self.lit.keys() {
+            if !seen.insert(*start) {
+                continue;
+            }
+            components += 1;
+            let mut stack = vec![*start];
+            while let Some((x, y)) = stack.pop() {
+                for (dx, dy) in NEIGHBOURS.iter() {
+                    let next = (x + dx, y + dy);
+                    if self.lit.contains_key(&next) && seen.insert(next) {
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        components
+    }
+
+    fn is_aligned(&self, criteria : &Alignment) -> bool {
+        self.neighboured_fraction() >= criteria.neighboured &&
+            criteria.max_components.is_none_or(|max| self.components() <= max)
+    }
+}
+
+// steps second by second to the first that meets the criteria
+fn scan(lights : &[Light], criteria : &Alignment, max_time : i32) -> Option<i32> {
+    let mut sky = Sky::at(lights, 0);
+    while !sky.is_aligned(criteria) {
+        if sky.time >= max_time {
+            return None;
+        }
+        sky.advance();
+    }
+    Some(sky.time)
+}
+
+// crops the points to their bounding box and scales it by a whole factor, up or down, so
+// the longer side fills at most max_size cells. A scaled down cell is lit if any point is in it.
+fn rasterise(points : &[(i32,i32)], max_size : usize) -> (usize, usize, Vec<bool>) {
+    let (min_x, max_x) = points.iter().map(|(x,_)| *x as i64).minmax().into_option().unwrap_or((0, -1));
+    let (min_y, max_y) = points.iter().map(|(_,y)| *y as i64).minmax().into_option().unwrap_or((0, -1));
+    let (w, h) = (max_x - min_x + 1, max_y - min_y + 1);
+    let longest = w.max(h).max(1);
+    let max_size = max_size.max(1) as i64;
+    let (up, down) = if longest > max_size { (1, (longest + max_size - 1) / max_size) } else { (max_size / longest, 1) };
+    let width = ((w + down - 1) / down * up) as usize;
+    let height = ((h + down - 1) / down * up) as usize;
+    let mut cells = vec![false; width * height];
+    for (x, y) in points {
+        let cx = ((*x as i64 - min_x) / down * up) as usize;
+        let cy = ((*y as i64 - min_y) / down * up) as usize;
+        for dy in 0..up as usize {
+            for dx in 0..up as usize {
+                cells[(cy + dy) * width + cx + dx] = true;
+            }
+        }
+    }
+    (width, height, cells)
+}
+
+// a binary PBM of the points, see rasterise for the cropping and scaling
+fn to_pbm(points : &[(i32,i32)], max_size : usize) -> Vec<u8> {
+    let (width, height, cells) = rasterise(points, max_size);
+    let mut image = format!("P4\n{} {}\n", width, height).into_bytes();
+    for row in cells.chunks(width.max(1)).take(height) {
+        for byte in row.chunks(8) {
+            image.push(byte.iter().enumerate().fold(0u8, |b, (i, lit)| if *lit { b | 0x80 >> i } else { b }));
+        }
+    }
+    image
+}
+
+// a fixed-height bitmap font, each glyph given as rows of '#' (lit) and '.' (dark)
+struct Font {
+    height : usize,
+    glyphs : HashMap<Vec<String>, char>
+}
+
+impl Font {
+    fn new(height : usize) -> Font {
+        Font { height, glyphs : HashMap::new() }
+    }
+
+    fn with_glyph(mut self, c : char, rows : &[&str]) -> Font {
+        assert_eq!(rows.len(), self.height, "glyph {} should be {} rows high", c, self.height);
+        let rows : Vec<String> = rows.iter().map(|r| r.to_string()).collect();
+        self.glyphs.insert(trim_columns(&rows), c);
+        self
+    }
+
+    // the 6x10 capitals the puzzle draws its messages in
+    fn standard() -> Font {
+        Font::new(10)
+            .with_glyph('A', &["..##..", ".#..#.", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#", "#....#"])
+            .with_glyph('B', &["#####.", "#....#", "#....#", "#....#", "#####.", "#....#", "#....#", "#....#", "#....#", "#####."])
+            .with_glyph('C', &[".####.", "#....#", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#....#", ".####."])
+            .with_glyph('E', &["######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....", "#.....", "######"])
+            .with_glyph('F', &["######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....", "#.....", "#....."])
+            .with_glyph('G', &[".####.", "#....#", "#.....", "#.....", "#.....", "#..###", "#....#", "#....#", "#...##", ".###.#"])
+            .with_glyph('H', &["#....#", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#", "#....#", "#....#"])
+            .with_glyph('J', &["...###", "....#.", "....#.", "....#.", "....#.", "....#.", "....#.", "#...#.", "#...#.", ".###.."])
+            .with_glyph('K', &["#....#", "#...#.", "#..#..", "#.#...", "##....", "##....", "#.#...", "#..#..", "#...#.", "#....#"])
+            .with_glyph('L', &["#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "######"])
+            .with_glyph('N', &["#....#", "##...#", "##...#", "#.#..#", "#.#..#", "#..#.#", "#..#.#", "#...##", "#...##", "#....#"])
+            .with_glyph('P', &["#####.", "#....#", "#....#", "#....#", "#####.", "#.....", "#.....", "#.....", "#.....", "#....."])
+            .with_glyph('R', &["#####.", "#....#", "#....#", "#....#", "#####.", "#..#..", "#...#.", "#...#.", "#....#", "#....#"])
+            .with_glyph('X', &["#....#", "#....#", ".#..#.", ".#..#.", "..##..", "..##..", ".#..#.", ".#..#.", "#....#", "#....#"])
+            .with_glyph('Z', &["######", ".....#", ".....#", "....#.", "...#..", "..#...", ".#....", "#.....", "#.....", "######"])
+    }
+}
+
+// drops the blank columns either side of a glyph
+fn trim_columns(rows : &[String]) -> Vec<String> {
+    let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let lit = |c : usize| rows.iter().any(|r| r.as_bytes().get(c) == Some(&b'#'));
+    match ((0..width).find(|c| lit(*c)), (0..width).rev().find(|c| lit(*c))) {
+        (Some(first), Some(last)) => rows.iter().map(|r| (first..=last).map(|c| if r.as_bytes().get(c) == Some(&b'#') { '#' } else { '.' }).collect()).collect(),
+        _ => rows.iter().map(|_| String::new()).collect()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum OcrError {
+    NoPoints,
+    WrongHeight { expected : usize, found : usize },
+    // the message with '?' in place of each glyph the font doesn't know, and those glyphs
+    Unrecognised { message : String, glyphs : Vec<Vec<String>> }
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OcrError::NoPoints => write!(f, "no points to read"),
+            OcrError::WrongHeight { expected, found } => write!(f, "message is {} rows high but the font is {}", found, expected),
+            OcrError::Unrecognised { message, glyphs } => {
+                writeln!(f, "read {} but could not recognise {} glyph(s):", message, glyphs.len())?;
+                for glyph in glyphs {
+                    writeln!(f)?;
+                    for row in glyph {
+                        writeln!(f, "{}", row)?;
+                    }
+                }
+                Ok(())
             }
         }
     }
-    true
+}
+
+// reads the message spelt out by the points, splitting glyphs on blank columns
+fn recognise(points : &[(i32,i32)], font : &Font) -> Result<String, OcrError> {
+    let (min_x, max_x) = points.iter().map(|(x,_)| *x).minmax().into_option().ok_or(OcrError::NoPoints)?;
+    let (min_y, max_y) = points.iter().map(|(_,y)| *y).minmax().into_option().unwrap();
+    let height = (max_y - min_y + 1) as usize;
+    if height != font.height {
+        return Err(OcrError::WrongHeight { expected : font.height, found : height });
+    }
+    let lit : HashSet<(i32,i32)> = points.iter().cloned().collect();
+    let column_lit = |x : i32| (min_y..=max_y).any(|y| lit.contains(&(x, y)));
+    let mut message = String::new();
+    let mut unknown = vec![];
+    let mut x = min_x;
+    while x <= max_x {
+        let start = x;
+        while x <= max_x && column_lit(x) {
+            x += 1;
+        }
+        let glyph : Vec<String> = (min_y..=max_y)
+            .map(|y| (start..x).map(|gx| if lit.contains(&(gx, y)) { '#' } else { '.' }).collect())
+            .collect();
+        match font.glyphs.get(&glyph) {
+            Some(c) => message.push(*c),
+            None => {
+                message.push('?');
+                unknown.push(glyph);
+            }
+        }
+        while x <= max_x && !column_lit(x) {
+            x += 1;
+        }
+    }
+    if unknown.is_empty() {
+        Ok(message)
+    } else {
+        Err(OcrError::Unrecognised { message, glyphs : unknown })
+    }
 }
 
 #[cfg(test)]
@@ -142,9 +486,90 @@ mod test {
         display(&split(&step(&input, 0)));
         display(&split(&step(&input, 1)));
         display(&split(&step(&input, 2)));
-        let step3 = split(&step(&input, 3));
-        display(&step3);
-        assert!(aligned(&step3));
+        display(&split(&step(&input, 3)));
+        assert!(!Sky::at(&input, 2).is_aligned(&Alignment::default()));
+        assert!(Sky::at(&input, 3).is_aligned(&Alignment::default()));
+        assert_eq!(scan(&input, &Alignment::default(), 100), Some(3));
+        assert_eq!(scan(&input, &Alignment::default(), 2), None);
+    }
+
+    #[test]
+    fn test_sky() {
+        let input = parse_file("example.txt");
+        let mut sky = Sky::at(&input, 0);
+        for t in 1..=4 {
+            sky.advance();
+            let expected = Sky::at(&input, t);
+            assert_eq!(sky.time, t);
+            assert_eq!(sky.positions, expected.positions);
+            assert_eq!(sky.lit, expected.lit);
+        }
+        let sky = Sky::at(&input, 3);
+        assert_eq!(sky.components(), 2);
+        assert_eq!(sky.neighboured_fraction(), 1.0);
+        // the H and the I drift apart into more pieces a second later
+        assert!(Sky::at(&input, 4).components() > 2);
+        assert!(Sky::at(&input, 2).neighboured_fraction() < 1.0);
+        let loose = Alignment { neighboured : 0.5, max_components : Some(2) };
+        assert_eq!(scan(&input, &loose, 100), Some(3));
+    }
+
+    #[test]
+    fn test_converge() {
+        let input = parse_file("example.txt");
+        assert_eq!(converge(&input, 100), Ok(3));
+        assert_eq!(converge(&input, 3), Ok(3));
+        assert_eq!(converge(&input, 2), Err(ConvergeError::TimeLimit(2)));
+        assert_eq!(converge(&[], 100), Err(ConvergeError::NoLights));
     }
-}
 
+    #[test]
+    fn test_converge_far_start() {
+        // a square of lights meeting at the origin after a million seconds
+        let input : Vec<Light> = vec![ ((-1_000_000, -1_000_000), (1, 1)), ((1_000_001, -1_000_000), (-1, 1)),
+                                       ((-1_000_000, 1_000_001), (1, -1)), ((1_000_001, 1_000_001), (-1, -1)) ];
+        assert_eq!(converge(&input, i32::MAX), Ok(1_000_000));
+        assert!(Sky::at(&input, 1_000_000).is_aligned(&Alignment::default()));
+    }
+
+    #[test]
+    fn test_recognise() {
+        let input = parse_file("input");
+        assert_eq!(recognise(&step(&input, 10515), &Font::standard()), Ok("GPJLLLLH".to_string()));
+        assert_eq!(recognise(&[], &Font::standard()), Err(OcrError::NoPoints));
+    }
+
+    #[test]
+    fn test_recognise_custom_font() {
+        // the example message is drawn 8 rows high
+        let input = step(&parse_file("example.txt"), 3);
+        let h = ["#...#", "#...#", "#...#", "#####", "#...#", "#...#", "#...#", "#...#"];
+        let i = ["###", ".#.", ".#.", ".#.", ".#.", ".#.", ".#.", "###"];
+        let font = Font::new(8).with_glyph('H', &h).with_glyph('I', &i);
+        assert_eq!(recognise(&input, &font), Ok("HI".to_string()));
+        assert_eq!(recognise(&input, &Font::standard()), Err(OcrError::WrongHeight { expected : 10, found : 8 }));
+        let glyphs = vec![i.iter().map(|r| r.to_string()).collect()];
+        assert_eq!(recognise(&input, &Font::new(8).with_glyph('H', &h)),
+                   Err(OcrError::Unrecognised { message : "H?".to_string(), glyphs }));
+    }
+
+    #[test]
+    fn test_to_pbm() {
+        let input = step(&parse_file("example.txt"), 3);
+        let image = to_pbm(&input, 10);
+        assert!(image.starts_with(b"P4\n10 8\n"));
+        // the top row of HI, #...#..###
+        assert_eq!(&image[8..10], &[0b1000_1001, 0b1100_0000]);
+        assert_eq!(image.len(), 8 + 8 * 2);
+    }
+
+    #[test]
+    fn test_to_pbm_scaling() {
+        assert_eq!(to_pbm(&[(5, 5)], 4), b"P4\n4 4\n\xf0\xf0\xf0\xf0".to_vec());
+        let image = to_pbm(&[(0, 0), (999, 0)], 100);
+        assert!(image.starts_with(b"P4\n100 1\n"));
+        assert_eq!(image[9], 0x80);
+        assert_eq!(image[21], 0x10);
+        assert_eq!(image.len(), 9 + 13);
+    }
+}
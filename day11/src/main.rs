@@ -1,75 +1,160 @@
-use std::collections::HashMap;
-fn calculate_power(x : u32, y : u32, serial_number : u32) -> i32 {
-    let rack_id=x+10;
-    let power_level = (((rack_id)*y) + serial_number) * rack_id;
-    let tmp = power_level.to_string();
-    if tmp.len() >= 3 {
-        let n = tmp.chars().nth(tmp.len() - 3).unwrap().to_digit(10).unwrap() as i32;
-        n - 5
-    } else {
-        -5
-    }
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+
+// the hundreds digit of the power level, less 5
+fn calculate_power(x : i64, y : i64, serial_number : i64) -> i64 {
+    let rack_id = x + 10;
+    let power_level = (rack_id * y + serial_number) * rack_id;
+    (power_level / 100).rem_euclid(10) - 5
+}
+
+// the fuel cells for a serial number, held as a summed-area table so the
+// power of any square is four lookups
+struct PowerGrid {
+    width : usize,
+    height : usize,
+    // sums[y][x] is the total power of the cells above and left of (x, y), exclusive
+    sums : Vec<Vec<i64>>
 }
 
-fn max_grid_pos(serial_number: u32, size : u32, memo : &HashMap::<(u32,u32), i32>) -> ((u32, u32), i32, HashMap::<(u32,u32), i32>) {
-    let mut max = 0;
-    let mut result = (0,0);
-    let mut power = HashMap::<(u32,u32), i32>::new();
-    let mut grid_value = 0;
-    for x in 1..(300 - size) {
-        for y in 1..(300-size) {
-            match memo.get(&(x,y)) {
-                Some(g) => {
-                    grid_value = *g;
-                    for dx in 0..size {
-                        grid_value = grid_value + calculate_power(x+dx, y+size-1, serial_number);
-                    }
-                    for dy in 0..size-1 {
-                        grid_value = grid_value + calculate_power(x+size-1, y+dy, serial_number);
-                    }
-                },
-                None => {
-                    grid_value = 0;
-                    for dx in 0..size {
-                        for dy in 0..size {
-                            grid_value = grid_value + calculate_power(x+dx, y+dy, serial_number);
-                        }
-                    }
+impl PowerGrid {
+    // the puzzle's 300x300 grid
+    fn new(serial_number : i64) -> PowerGrid {
+        PowerGrid::with_size(serial_number, 300, 300)
+    }
+
+    fn with_size(serial_number : i64, width : usize, height : usize) -> PowerGrid {
+        let mut sums = vec![vec![0; width + 1]; height + 1];
+        for y in 1..=height {
+            for x in 1..=width {
+                sums[y][x] = calculate_power(x as i64, y as i64, serial_number)
+                    + sums[y - 1][x] + sums[y][x - 1] - sums[y - 1][x - 1];
+            }
+        }
+        PowerGrid { width, height, sums }
+    }
+
+    // total power of the square with its top left at (x, y), counting from 1
+    fn square_power(&self, x : usize, y : usize, size : usize) -> i64 {
+        let (x0, y0, x1, y1) = (x - 1, y - 1, x - 1 + size, y - 1 + size);
+        self.sums[y1][x1] - self.sums[y0][x1] - self.sums[y1][x0] + self.sums[y0][x0]
+    }
+
+    fn cell_power(&self, x : usize, y : usize) -> i64 {
+        self.square_power(x, y, 1)
+    }
+
+    // the power levels within radius of (x, y), clipped to the grid, laid out as in the puzzle
+    fn window(&self, x : usize, y : usize, radius : usize) -> String {
+        let mut result = String::new();
+        for wy in y.saturating_sub(radius).max(1)..=(y + radius).min(self.height) {
+            let row : Vec<String> = (x.saturating_sub(radius).max(1)..=(x + radius).min(self.width))
+                .map(|wx| format!("{:>2}", self.cell_power(wx, wy)))
+                .collect();
+            result.push_str(&row.join("  "));
+            result.push('\n');
+        }
+        result
+    }
+
+    // the most powerful square of the given size as (x, y, size, power), the first
+    // in reading order winning ties
+    fn best_square(&self, size : usize) -> Option<(usize, usize, usize, i64)> {
+        if size == 0 || size > self.width || size > self.height {
+            return None;
+        }
+        let mut best : Option<(usize, usize, usize, i64)> = None;
+        for y in 1..=(self.height - size + 1) {
+            for x in 1..=(self.width - size + 1) {
+                let power = self.square_power(x, y, size);
+                if best.is_none_or(|(_, _, _, p)| power > p) {
+                    best = Some((x, y, size, power));
                 }
-            };
-            power.insert((x,y), grid_value);
-            if grid_value > max {
-               result = (x,y);
-               max = grid_value;
             }
         }
+        best
+    }
+
+    // the most powerful square of any size, the smallest size winning ties
+    fn best_square_any_size(&self) -> Option<(usize, usize, usize, i64)> {
+        let mut best = self.best_square(1)?;
+        for size in 2..=self.width.min(self.height) {
+            let square = self.best_square(size).unwrap();
+            if square.3 > best.3 {
+                best = square;
+            }
+        }
+        Some(best)
     }
-    (result, max, power)
 }
 
-fn max_grid_pos_any_size(serial_number: u32) -> ((u32, u32), u32) {
-    let mut max = 0;
-    let mut result = (0,0);
-    let mut grid_size = 0;
-    let mut memo = HashMap::<(u32,u32), i32>::new();
-    for n in 1..300 {
-        let (r, m, updated) = max_grid_pos(serial_number, n, &memo);
-        memo = updated;
-        if m >= max {
-          result = r;
-          max = m;
-          grid_size = n;
-        } else {
-          return (result, grid_size)
+// a P6 heatmap with a pixel per cell, running from blue at -5 through white to red at 4,
+// with the given square outlined in green
+fn render_ppm(grid : &PowerGrid, outline : Option<(usize, usize, usize, i64)>) -> Vec<u8> {
+    let mut image = format!("P6\n{} {}\n255\n", grid.width, grid.height).into_bytes();
+    for y in 1..=grid.height {
+        for x in 1..=grid.width {
+            let on_outline = outline.is_some_and(|(ox, oy, size, _)| {
+                let inside = x >= ox && x < ox + size && y >= oy && y < oy + size;
+                inside && (x == ox || x == ox + size - 1 || y == oy || y == oy + size - 1)
+            });
+            let colour = if on_outline {
+                [0, 255, 0]
+            } else {
+                let power = grid.cell_power(x, y);
+                if power < 0 {
+                    let fade = (255 * (5 + power) / 5) as u8;
+                    [fade, fade, 255]
+                } else {
+                    let fade = (255 * (4 - power) / 4) as u8;
+                    [255, fade, fade]
+                }
+            };
+            image.extend_from_slice(&colour);
         }
     }
-    (result, grid_size)    
+    image
 }
 
+// usage: day11 [serial number] [width] [height]
+//        day11 render [serial number] [square size] [file]
+//        day11 window [serial number] [x] [y]
 fn main() {
-    let (r,_,_) = max_grid_pos(6548, 3, &HashMap::<(u32,u32), i32>::new());
-    println!("Part 1 - {:?}", r);
-    println!("Part 2 - {:?}", max_grid_pos_any_size(6548));
+    let args : Vec<String> = env::args().skip(1).collect();
+    let number = |i : usize, default : i64| args.get(i).map_or(default, |a| a.parse().expect("arguments should be numbers"));
+    match args.first().map(|a| a.as_str()) {
+        Some("render") => {
+            let grid = PowerGrid::new(number(1, 6548));
+            let best = grid.best_square(number(2, 3) as usize);
+            let filename = args.get(3).map_or("day11.ppm", |a| a.as_str());
+            let mut f = File::create(filename).expect("unable to create image");
+            f.write_all(&render_ppm(&grid, best)).unwrap();
+            if let Some((x, y, size, power)) = best {
+                println!("Outlined the {0}x{0} square at {1},{2} with power {3}", size, x, y, power);
+            }
+            return;
+        },
+        Some("window") => {
+            let grid = PowerGrid::new(number(1, 6548));
+            print!("{}", grid.window(number(2, 1) as usize, number(3, 1) as usize, 2));
+            return;
+        },
+        _ => {}
+    }
+    let args : Vec<i64> = (0..args.len()).map(|i| number(i, 0)).collect();
+    let serial_number = args.first().cloned().unwrap_or(6548);
+    let grid = match (args.get(1), args.get(2)) {
+        (None, _) => PowerGrid::new(serial_number),
+        (Some(width), height) => PowerGrid::with_size(serial_number, *width as usize, *height.unwrap_or(width) as usize)
+    };
+    match grid.best_square(3) {
+        Some((x, y, _, _)) => println!("Part 1 - {:?}", (x, y)),
+        None => println!("Part 1 - grid is smaller than 3x3")
+    }
+    if let Some((x, y, size, _)) = grid.best_square_any_size() {
+        println!("Part 2 - {:?}", ((x, y), size));
+    }
 }
 
 #[cfg(test)] 
@@ -82,19 +167,71 @@ mod test {
         assert_eq!(calculate_power(122,79,57), -5);
         assert_eq!(calculate_power(217,196,39), 0);
         assert_eq!(calculate_power(101,153,71), 4);
+        assert_eq!(calculate_power(300,300,10_000_000_000), -5);
     }
 
     #[test]
     fn test_max_grid(){
-        let (r,m,_) = max_grid_pos(18, 3, &HashMap::new());
-        assert_eq!(r, (33,45));    
-        assert_eq!(m, 29);        
+        let grid = PowerGrid::new(18);
+        assert_eq!(grid.best_square(3), Some((33,45,3,29)));
+        assert_eq!(PowerGrid::new(42).best_square(3), Some((21,61,3,30)));
+        assert_eq!(grid.best_square(301), None);
+    }
+
+    #[test]
+    fn test_square_power(){
+        let grid = PowerGrid::new(18);
+        let brute : i64 = (33..36).flat_map(|x| (45..48).map(move |y| calculate_power(x, y, 18))).sum();
+        assert_eq!(grid.square_power(33, 45, 3), brute);
+        assert_eq!(grid.square_power(300, 300, 1), calculate_power(300, 300, 18));
     }
 
     #[test]
     fn test_max_grid_pos_any_size(){
-        let (r,n) = max_grid_pos_any_size(18);
-        assert_eq!(r, (90,269));    
-        assert_eq!(n, 16);        
+        assert_eq!(PowerGrid::new(18).best_square_any_size(), Some((90,269,16,113)));
+        assert_eq!(PowerGrid::new(42).best_square_any_size(), Some((232,251,12,119)));
+    }
+
+    #[test]
+    fn test_grid_size(){
+        // a tall thin grid only fits squares up to its width, and the bottom right one counts
+        let grid = PowerGrid::with_size(18, 4, 10);
+        assert_eq!(grid.best_square(5), None);
+        let total : i64 = (1..=4).flat_map(|x| (7..=10).map(move |y| calculate_power(x, y, 18))).sum();
+        assert_eq!(grid.square_power(1, 7, 4), total);
+        let brute = (1..=4).flat_map(|size| (1..=(11 - size)).flat_map(move |y| (1..=(5 - size)).map(move |x| (size, y, x))))
+            .map(|(size, y, x)| grid.square_power(x, y, size)).max().unwrap();
+        assert_eq!(grid.best_square_any_size().unwrap().3, brute);
+        assert_eq!(PowerGrid::with_size(18, 0, 0).best_square_any_size(), None);
+    }
+
+    #[test]
+    fn test_window(){
+        let grid = PowerGrid::new(18);
+        let expected = concat!("-2  -4   4   4   4\n",
+                               "-4   4   4   4  -5\n",
+                               " 4   3   3   4  -4\n",
+                               " 1   1   2   4  -3\n",
+                               "-1   0   2  -5  -2\n");
+        assert_eq!(grid.window(34, 46, 2), expected);
+        // clipped at the top left corner
+        assert_eq!(grid.window(1, 1, 1).lines().count(), 2);
+        assert_eq!(grid.window(1, 1, 1).lines().next().unwrap().split_whitespace().count(), 2);
+    }
+
+    #[test]
+    fn test_render_ppm(){
+        let grid = PowerGrid::with_size(18, 4, 4);
+        let image = render_ppm(&grid, Some((2, 2, 2, 0)));
+        let header = b"P6\n4 4\n255\n";
+        assert!(image.starts_with(header));
+        assert_eq!(image.len(), header.len() + 4 * 4 * 3);
+        let pixel = |x : usize, y : usize| { let i = header.len() + ((y - 1) * 4 + x - 1) * 3; &image[i..i + 3] };
+        assert_eq!(pixel(2, 2), &[0, 255, 0]);
+        assert_eq!(pixel(3, 3), &[0, 255, 0]);
+        assert_ne!(pixel(1, 1), &[0, 255, 0]);
+        // -2 is three fifths of the way from blue to white
+        assert_eq!(grid.cell_power(1, 1), -2);
+        assert_eq!(pixel(1, 1), &[153, 153, 255]);
     }
 }
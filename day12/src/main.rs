@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 
@@ -10,12 +13,12 @@ fn parse_file(filename : &str) -> Result<(String, Vec<Rule>), std::io::Error> {
     let mut f = File::open(filename).expect("File not found");
     let mut contents = String::new();
     f.read_to_string(&mut contents)?;
-    let mut lines = contents.trim().split("\n");
+    let mut lines = contents.trim().split('\n');
     let mut rules = Vec::<Rule>::new();
-    let initial : String = lines.next().unwrap().split(" ").nth(2).unwrap().to_owned();
+    let initial : String = lines.next().unwrap().split(' ').nth(2).unwrap().to_owned();
     lines.next();
-    while let Some(line) = lines.next() {
-        let mut elems = line.split(" ");
+    for line in lines {
+        let mut elems = line.split(' ');
         let m = elems.next().unwrap().to_owned();
         let a = elems.nth(1).unwrap() == "#";
         rules.push(Rule {matches : m, action: a});
@@ -23,18 +26,18 @@ fn parse_file(filename : &str) -> Result<(String, Vec<Rule>), std::io::Error> {
     Ok((initial, rules))
 }
 
-fn apply_rules(entry : &str, rules : &Vec<Rule>) -> bool {
+fn apply_rules(entry : &str, rules : &[Rule]) -> bool {
     for rule in rules {
         if rule.matches == entry {
             return rule.action;
         }
     }
-    return false;
+    false
 }
 
-fn update_state(state : &String, offset: i64, rules : &Vec<Rule>) -> (String, i64) {
+fn update_state(state : &str, offset: i64, rules : &[Rule]) -> (String, i64) {
     let mut input_state : String = "....".to_owned();
-    input_state.push_str(&state);
+    input_state.push_str(state);
     input_state.push_str("....");
     let mut output_state = String::new();
     for pos in 0..state.len()+4 {
@@ -58,27 +61,119 @@ fn update_state(state : &String, offset: i64, rules : &Vec<Rule>) -> (String, i6
     }
 }
 
-fn pt1( state : &String, rules : &Vec<Rule> ) -> i64 {
+// the rules as a 32 bit table, bit n giving the outcome for the neighbourhood that
+// reads as n in binary with '#' as 1, so "##..#" is bit 25
+fn rule_table(rules : &[Rule]) -> u32 {
+    rules.iter().filter(|r| r.action).fold(0, |table, r| table | 1 << neighbourhood(&r.matches))
+}
+
+fn neighbourhood(pattern : &str) -> u32 {
+    pattern.chars().fold(0, |n, c| n << 1 | (c == '#') as u32)
+}
+
+// a row of pots as a bitset, bit i being the pot numbered first + i. Always trimmed
+// so the first and last pots hold plants, unless there are none at all.
+#[derive(Clone, Debug, PartialEq)]
+struct Pots {
+    first : i64,
+    len : usize,
+    words : Vec<u64>
+}
+
+impl Pots {
+    fn new(first : i64) -> Pots {
+        Pots { first, len : 0, words : vec![] }
+    }
+
+    // pots as in the puzzle input, with the leftmost numbered -offset
+    fn parse(state : &str, offset : i64) -> Pots {
+        let mut pots = Pots::new(-offset);
+        state.chars().for_each(|c| pots.push(c == '#'));
+        pots.trim_end();
+        pots
+    }
+
+    fn get(&self, i : usize) -> bool {
+        i < self.len && self.words[i / 64] >> (i % 64) & 1 == 1
+    }
+
+    // adds the next pot on the right, skipping empty pots on the left
+    fn push(&mut self, plant : bool) {
+        if self.len == 0 && !plant {
+            self.first += 1;
+            return;
+        }
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+        if plant {
+            self.words[self.len / 64] |= 1 << (self.len % 64);
+        }
+        self.len += 1;
+    }
+
+    fn trim_end(&mut self) {
+        while self.len > 0 && !self.get(self.len - 1) {
+            self.len -= 1;
+        }
+        self.words.truncate(self.len.div_ceil(64));
+    }
+
+    // the next generation, rolling the five pot neighbourhood along the row. Pots more
+    // than two beyond the plants never change, as an empty neighbourhood stays empty.
+    fn step(&self, table : u32) -> Pots {
+        let mut next = Pots::new(self.first - 2);
+        let mut n = 0;
+        for i in 0..self.len + 4 {
+            n = (n << 1 | self.get(i) as u32) & 31;
+            next.push(table >> n & 1 == 1);
+        }
+        next.trim_end();
+        next
+    }
+
+    fn plants(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.len).filter(move |i| self.get(*i)).map(move |i| self.first + i as i64)
+    }
+
+    fn score(&self) -> i64 {
+        self.plants().sum()
+    }
+}
+
+impl fmt::Display for Pots {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        let row : String = (0..self.len).map(|i| if self.get(i) { '#' } else { '.' }).collect();
+        write!(f, "{}", row)
+    }
+}
+
+fn pt1( state : &str, rules : &[Rule] ) -> i64 {
     calculate_generation(state, rules, 20)
 }
 
-fn pt2( state : &String, rules : &Vec<Rule> ) -> i64 {
-    if let Some((score, offset, diff)) = find_stable_generation(state, rules, 300) {
-        return score + ((50000000000 - offset - 1) * diff);
-    } 
-    panic!("No stable value detected");
+fn pt2( state : &str, rules : &[Rule] ) -> Result<i64, CycleError> {
+    Ok(find_cycle(state, rules, 1000)?.score_at(50000000000))
+}
+
+fn calculate_generation( state : &str, rules : &[Rule], generations : u32 ) -> i64 {
+    run_pots(state, rules, generations).score()
 }
 
-fn calculate_generation( state : &String, rules : &Vec<Rule>, generations : u32 ) -> i64 {
-    let (updated_state, offset) = run_generations(state, rules, generations);
-    score_generation(updated_state, offset)
+fn run_pots( state : &str, rules : &[Rule], generations : u32 ) -> Pots {
+    let table = rule_table(rules);
+    let mut pots = Pots::parse(state, 0);
+    for _ in 0..generations {
+        pots = pots.step(table);
+    }
+    pots
 }
 
-fn run_generations( state : &String, rules : &Vec<Rule>, generations : u32 ) -> (String, i64) {
+fn run_generations( state : &str, rules : &[Rule], generations : u32 ) -> (String, i64) {
     let mut offset = 0;
-    let mut updated_state = state.clone();
+    let mut updated_state = state.to_owned();
     for _ in 0..generations {
-        let (u, o) = update_state( &updated_state, offset, &rules );
+        let (u, o) = update_state( &updated_state, offset, rules );
         updated_state = u;
         offset = o;
     }
@@ -87,46 +182,105 @@ fn run_generations( state : &String, rules : &Vec<Rule>, generations : u32 ) ->
 
 fn score_generation(state : String, offset : i64) -> i64 {
     let mut sum : i64 = 0;
-    let mut pos = 0;
-    for i in 0..state.len() {
-        if state.chars().nth(i).unwrap() == '#' {
-            pos = i as i64 - offset as i64;
-            sum = sum + pos;
+    for (i, c) in state.chars().enumerate() {
+        if c == '#' {
+            sum += i as i64 - offset;
         }
     }
     sum
 }
 
-fn find_stable_generation( state : &String, rules : &Vec<Rule>, generations : u32 ) -> Option<(i64, i64, i64)> {
-    let mut offset = 0;
-    let mut updated_state = state.clone();
-    let mut last_score = 0;
-    let mut last_diff = 0;
-    let mut same_diff_count = 0;
-    for n in 0..generations {
-        let (u, o) = update_state( &updated_state, offset, &rules );
-        let score = score_generation(u.clone(), o);
-        let diff = score - last_score;
-        if last_diff == diff {
-            same_diff_count = same_diff_count + 1;
-        } else {
-            same_diff_count = 0;
+#[derive(Debug, PartialEq)]
+enum CycleError {
+    // the pattern didn't recur within this many generations
+    NoCycle(u32),
+    // "....." grows a plant, so every empty pot fills
+    EmptyPotsGrow
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CycleError::NoCycle(budget) => write!(f, "no repeating pattern within {} generations", budget),
+            CycleError::EmptyPotsGrow => write!(f, "the rules grow plants in empty pots, so there are infinitely many")
         }
-        if same_diff_count > 5 {
-            return Some((score, n as i64, diff));
+    }
+}
+
+// the pots from generation start onwards repeat every period generations, shifted by shift
+// pots each time. The rules only see neighbourhoods, so a recurring pattern behaves the same
+// wherever it is and this holds for every later generation.
+struct Cycle {
+    start : u64,
+    period : u64,
+    shift : i64,
+    // every generation up to the first repeat
+    history : Vec<Pots>
+}
+
+impl Cycle {
+    fn pots_at(&self, generation : u64) -> Pots {
+        if generation < self.start + self.period {
+            return self.history[generation as usize].clone();
         }
-        last_diff = diff;
-        last_score = score;
-        updated_state = u;
-        offset = o;
+        let (laps, into) = ((generation - self.start) / self.period, (generation - self.start) % self.period);
+        let mut pots = self.history[(self.start + into) as usize].clone();
+        pots.first += laps as i64 * self.shift;
+        pots
+    }
+
+    fn score_at(&self, generation : u64) -> i64 {
+        self.pots_at(generation).score()
+    }
+}
+
+// runs until a pattern of pots repeats, wherever it has moved to, giving up after budget generations
+fn find_cycle( state : &str, rules : &[Rule], budget : u32 ) -> Result<Cycle, CycleError> {
+    let table = rule_table(rules);
+    if table & 1 == 1 {
+        return Err(CycleError::EmptyPotsGrow);
     }
-    None
+    let mut pots = Pots::parse(state, 0);
+    // trimmed pots are the same pattern exactly when their bits are
+    let mut seen = HashMap::<(usize, Vec<u64>), u64>::new();
+    let mut history : Vec<Pots> = vec![];
+    for generation in 0..=budget as u64 {
+        if let Some(start) = seen.insert((pots.len, pots.words.clone()), generation) {
+            let shift = pots.first - history[start as usize].first;
+            return Ok(Cycle { start, period : generation - start, shift, history });
+        }
+        let next = pots.step(table);
+        history.push(pots);
+        pots = next;
+    }
+    Err(CycleError::NoCycle(budget))
+}
+
+// whether the string and bitset automata agree on the pots and score after the generations
+fn verify( state : &str, rules : &[Rule], generations : u32 ) -> bool {
+    let pots = run_pots(state, rules, generations);
+    let (row, offset) = run_generations(state, rules, generations);
+    Pots::parse(&row, offset) == pots && score_generation(row, offset) == pots.score()
 }
 
+// usage: day12 [verify [generations]]
 fn main() {
     let (initial, rules) = parse_file("input.txt").unwrap();
+    let args : Vec<String> = env::args().skip(1).collect();
+    if args.first().map(|a| a.as_str()) == Some("verify") {
+        let generations = args.get(1).map_or(1000, |g| g.parse().expect("generations should be a number"));
+        if verify(&initial, &rules, generations) {
+            println!("Bitset and string automata agree after {} generations", generations);
+        } else {
+            println!("Bitset and string automata differ after {} generations", generations);
+        }
+        return;
+    }
     println!("Part 1 - score after 20 generations is {:?}", pt1(&initial, &rules));
-    println!("Part 2 - score after 50000000000 generations is {:?}", pt2(&initial, &rules));
+    match pt2(&initial, &rules) {
+        Ok(score) => println!("Part 2 - score after 50000000000 generations is {:?}", score),
+        Err(e) => println!("Part 2 - {}", e)
+    }
 }
 
 #[cfg(test)] 
@@ -149,5 +303,71 @@ mod test {
         let expected = "#....##....#####...#######....#.#..##";
         assert_eq!(result, expected);
         assert_eq!(offset, 2);
+        assert_eq!(score_generation(result, offset), 325);
+    }
+
+    #[test]
+    fn test_run_pots() {
+        let (example, rules) = parse_file("example.txt").unwrap();
+        let pots = run_pots(&example, &rules, 20);
+        assert_eq!(pots.to_string(), "#....##....#####...#######....#.#..##");
+        assert_eq!(pots.first, -2);
+        assert_eq!(pots.score(), 325);
+        let (initial, input_rules) = parse_file("input.txt").unwrap();
+        for generations in [0, 1, 2, 3, 20, 50, 150] {
+            assert!(verify(&example, &rules, generations));
+            assert!(verify(&initial, &input_rules, generations));
+        }
+    }
+
+    #[test]
+    fn test_pots() {
+        assert_eq!(neighbourhood("##..#"), 25);
+        let pots = Pots::parse("..#.#..", 1);
+        assert_eq!(pots.first, 1);
+        assert_eq!(pots.to_string(), "#.#");
+        assert_eq!(pots.plants().collect::<Vec<_>>(), vec![1, 3]);
+        // crossing a word boundary
+        let long = Pots::parse(&format!("#{}#", ".".repeat(70)), 0);
+        assert_eq!(long.words.len(), 2);
+        assert_eq!(long.score(), 71);
+        assert_eq!(Pots::parse("...", 0).len, 0);
+    }
+
+    // the rules set in the table, see rule_table
+    fn rules_from_table(table : u32) -> Vec<Rule> {
+        (0..32).map(|n : u32| Rule {
+            matches : (0..5).rev().map(|b| if n >> b & 1 == 1 { '#' } else { '.' }).collect(),
+            action : table >> n & 1 == 1
+        }).collect()
+    }
+
+    #[test]
+    fn test_find_cycle() {
+        let (initial, rules) = parse_file("input.txt").unwrap();
+        let cycle = find_cycle(&initial, &rules, 1000).unwrap();
+        assert_eq!(cycle.period, 1);
+        assert_eq!(cycle.score_at(20), 2040);
+        assert_eq!(cycle.score_at(50000000000), 1700000000011);
+        for generation in [0, 20, cycle.start, cycle.start + 1, 300] {
+            assert_eq!(cycle.pots_at(generation), run_pots(&initial, &rules, generation as u32));
+        }
+        assert_eq!(find_cycle(&initial, &rules, 10).err(), Some(CycleError::NoCycle(10)));
+        let (example, rules) = parse_file("example.txt").unwrap();
+        assert_eq!(find_cycle(&example, &rules, 1000).unwrap().score_at(20), 325);
+    }
+
+    #[test]
+    fn test_find_longer_cycle() {
+        // "##.#" settles into a shape that moves three pots right every three generations
+        let rules = rules_from_table(4180941928);
+        let cycle = find_cycle("##.#", &rules, 100).unwrap();
+        assert_eq!((cycle.start, cycle.period, cycle.shift), (4, 3, 3));
+        for generation in 0..100 {
+            let pots = run_pots("##.#", &rules, generation);
+            assert_eq!(cycle.pots_at(generation as u64), pots);
+            assert_eq!(cycle.score_at(generation as u64), pots.score());
+        }
+        assert_eq!(find_cycle("#", &rules_from_table(1), 100).err(), Some(CycleError::EmptyPotsGrow));
     }
 }
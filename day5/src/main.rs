@@ -1,6 +1,50 @@
 
+use std::env;
 use std::fs::File;
 use std::io::prelude::*;
+use std::collections::BTreeMap;
+
+#[derive(Debug, PartialEq)]
+struct Reaction {
+    // each unit alongside its position in the original input
+    left : (char, usize),
+    right : (char, usize)
+}
+
+#[derive(Debug)]
+struct Trace {
+    result : String,
+    reactions : Vec<Reaction>,
+    // reactions per unit type, keyed by the lowercase unit
+    counts : BTreeMap<char, usize>
+}
+
+fn reacts( a : char, b : char ) -> bool {
+    a != b && a.eq_ignore_ascii_case(&b)
+}
+
+// single pass collapse using a stack of surviving units, so every reaction
+// can be recorded against the positions the units had in the input
+fn collapse_traced( s : &str ) -> Trace {
+    let mut stack : Vec<(char, usize)> = Vec::new();
+    let mut reactions = Vec::new();
+    let mut counts = BTreeMap::new();
+    for (pos, c) in s.chars().enumerate() {
+        match stack.last() {
+            Some(&(prev, _)) if reacts(prev, c) => {
+                let left = stack.pop().unwrap();
+                *counts.entry(c.to_ascii_lowercase()).or_insert(0) += 1;
+                reactions.push(Reaction { left, right : (c, pos) });
+            },
+            _ => stack.push((c, pos))
+        }
+    }
+    Trace {
+        result : stack.into_iter().map(|(c, _)| c).collect(),
+        reactions,
+        counts
+    }
+}
 
 fn collapse( s : &str ) -> String {
     let mut chars = s.chars();
@@ -15,7 +59,6 @@ fn collapse( s : &str ) -> String {
                 Some(n) => curr = n,
                 None => {
                     push_last = false; 
-                    ()
                 }
             }
         } else {
@@ -30,7 +73,7 @@ fn collapse( s : &str ) -> String {
     if recur {
         return collapse(&u);
     } 
-    return u;
+    u
 }
 
 fn remove_and_collapse(s : &str, ignore : char) -> String {
@@ -38,16 +81,16 @@ fn remove_and_collapse(s : &str, ignore : char) -> String {
     collapse(&cleaned)
 }
 
+// the collapsed length obtained after removing each unit type
+fn lengths_by_removed_unit(s: &str) -> Vec<(char, usize)> {
+    (b'a'..=b'z').map(|b| b as char)
+        .map(|c| (c, remove_and_collapse(s, c).len()))
+        .collect()
+}
+
 fn find_best(s: &str) -> String {
-    let mut best = s.to_string();
-    for c in vec!['a','b','c','d','e','f','g','h','i','j','k','l','m','n','o','p','q','r','s','t','u','v','w','x','y','z'] {
-        println!("Removing {}", c);
-        let result = remove_and_collapse(&s, c).clone();
-        if result.len() < best.len() {
-            best = result;
-        }
-    }
-    best
+    let (unit, _) = lengths_by_removed_unit(s).into_iter().min_by_key(|(_, len)| *len).unwrap();
+    remove_and_collapse(s, unit)
 }
 
 fn read_value_in_file(filename : &str) -> Result<String,std::io::Error> {
@@ -57,13 +100,31 @@ fn read_value_in_file(filename : &str) -> Result<String,std::io::Error> {
     Ok(contents)
 }
 
+// usage: day5 [trace]
 fn main() {
+    let args : Vec<String> = env::args().collect();
     let c = read_value_in_file("input");
     let s = c.unwrap();
+    if args.len() > 1 && args[1] == "trace" {
+        show_trace(&s);
+        return;
+    }
     println!("Pt1 {}", collapse(&s).len());
     println!("Pt2 {}", find_best(&s).len());
 }
 
+// every reaction counted by unit type, and what removing each unit leaves
+fn show_trace(s : &str) {
+    let trace = collapse_traced(s);
+    println!("Reactions {} leaving {} units", trace.reactions.len(), trace.result.len());
+    for (unit, count) in &trace.counts {
+        println!("  {} reacted {} times", unit, count);
+    }
+    for (unit, len) in lengths_by_removed_unit(s) {
+        println!("Removing {} gives {}", unit, len);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
@@ -88,4 +149,24 @@ mod tests {
     fn test_find_best(){        
         assert_eq!(find_best("dabAcCaCBAcCcaDA"), "daDA");
     }
+
+    #[test]
+    fn test_collapse_traced(){
+        let trace = collapse_traced("dabAcCaCBAcCcaDA");
+        assert_eq!(trace.result, collapse("dabAcCaCBAcCcaDA"));
+        assert_eq!(trace.reactions, vec![
+            Reaction { left : ('c', 4), right : ('C', 5) },
+            Reaction { left : ('A', 3), right : ('a', 6) },
+            Reaction { left : ('c', 10), right : ('C', 11) }
+        ]);
+        assert_eq!(trace.counts.get(&'a'), Some(&1));
+        assert_eq!(trace.counts.get(&'c'), Some(&2));
+        assert_eq!(trace.counts.get(&'b'), None);
+    }
+
+    #[test]
+    fn test_lengths_by_removed_unit(){
+        let lengths = lengths_by_removed_unit("dabAcCaCBAcCcaDA");
+        assert_eq!(&lengths[0..4], &[('a', 6), ('b', 8), ('c', 4), ('d', 6)]);
+    }
 }
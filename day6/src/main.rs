@@ -1,64 +1,239 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
+use std::ops::RangeInclusive;
+use std::time::Instant;
 
 fn read_lines_in_file(filename : &str) -> Result<Vec<(i32,i32)>,std::io::Error> {
     let mut f = File::open(filename).expect("file not found");
     let mut contents = String::new();
     f.read_to_string(&mut contents)?;
-    let lines = contents.to_string()
-                        .split('\n')
-                        .filter(|s| s.to_string() != "")
-                        .map(|s| parse_line(s) )
+    let lines = contents.split('\n')
+                        .filter(|s| !s.is_empty())
+                        .map(parse_line)
                         .collect();
     Ok(lines)
 }
 
 fn parse_line(s: &str) -> (i32, i32) {
-    let v : Vec<i32>= s.split(",").map(|s| s.trim().parse::<i32>().unwrap()).collect();
+    let v : Vec<i32>= s.split(',').map(|s| s.trim().parse::<i32>().unwrap()).collect();
     (v[0], v[1])
 }
 
+// usage: day6 [bench [count] [size] | render [file] | metrics]
 fn main() {
+    let args : Vec<String> = env::args().collect();
+    if args.len() > 1 && args[1] == "bench" {
+        let count = args.get(2).map_or(2000, |a| a.parse().unwrap());
+        let size = args.get(3).map_or(400, |a| a.parse().unwrap());
+        bench(count, size);
+        return;
+    }
     let entries = read_lines_in_file("input").unwrap();
-    println!("Pt1 {}", largest_finite(&entries));
-    println!("Pt2 {}", region_size_by_distance(&entries, 10000));
+    if args.len() > 1 && args[1] == "render" {
+        let grid = populate_grid(&entries, &Manhattan, 1);
+        println!("{}", render_map(&entries, &grid));
+        let filename = args.get(2).map_or("day6.ppm", |a| a.as_str());
+        let mut f = File::create(filename).expect("unable to create image");
+        f.write_all(&render_ppm(&entries, &grid, &Manhattan, 10000)).unwrap();
+        return;
+    }
+    if args.len() > 1 && args[1] == "metrics" {
+        show_metrics(&entries);
+        return;
+    }
+    println!("Pt1 {}", largest_finite(&entries, &Manhattan).unwrap());
+    println!("Pt2 {}", region_size_by_distance(&entries, 10000, &Manhattan));
+}
+
+// the puzzle answers under the other metrics, and for a larger safe distance
+fn show_metrics(entries : &[(i32, i32)]) {
+    println!("Chebyshev {} {}", show_largest_finite(entries, &Chebyshev), region_size_by_distance(entries, 10000, &Chebyshev));
+    println!("Squared euclidean {} {}", show_largest_finite(entries, &SquaredEuclidean), region_size_by_distance(entries, 10000000, &SquaredEuclidean));
+    println!("Pt2 with 100000 {}", region_size_by_distance(entries, 100000, &Manhattan));
+}
+
+// the largest finite region, or why it could not be found
+fn show_largest_finite<M : Metric>(entries : &[(i32, i32)], metric : &M) -> String {
+    largest_finite(entries, metric).map_or_else(|e| e.to_string(), |size| size.to_string())
+}
+
+// a distance measure between two grid coordinates. Distances are expected to be
+// convex and never less than the difference along either axis, which bounds how
+// far from the coordinates a region can reach.
+trait Metric {
+    fn distance(&self, x1 : (i32, i32), x2 : (i32, i32)) -> i64;
+
+    // the unit moves whose shortest path length matches this metric, if it has any,
+    // which allows the grid to be labelled with a flood fill
+    fn steps(&self) -> Option<&'static [(i32, i32)]> {
+        None
+    }
+
+    // whether the distance is the sum of the distances along each axis
+    fn is_separable(&self) -> bool {
+        false
+    }
+
+    // how far beyond the bounding box of the coordinates the owner along every
+    // outward ray stops changing
+    fn settled_margin(&self, width : i32, height : i32) -> i32;
+}
+
+struct Manhattan;
+struct Chebyshev;
+struct SquaredEuclidean;
+
+impl Metric for Manhattan {
+    fn distance(&self, x1 : (i32, i32), x2 : (i32, i32)) -> i64 {
+        distance(x1, x2) as i64
+    }
+
+    fn steps(&self) -> Option<&'static [(i32, i32)]> {
+        Some(&[(0,-1), (-1,0), (1,0), (0,1)])
+    }
+
+    fn is_separable(&self) -> bool {
+        true
+    }
+
+    // outside the bounding box every step away adds one to the distance of
+    // every coordinate, so nothing changes owner past the first ring
+    fn settled_margin(&self, _width : i32, _height : i32) -> i32 {
+        1
+    }
+}
+
+impl Metric for Chebyshev {
+    fn distance(&self, x1 : (i32, i32), x2 : (i32, i32)) -> i64 {
+        (x1.0 - x2.0).abs().max((x1.1 - x2.1).abs()) as i64
+    }
+
+    fn steps(&self) -> Option<&'static [(i32, i32)]> {
+        Some(&[(-1,-1), (0,-1), (1,-1), (-1,0), (1,0), (-1,1), (0,1), (1,1)])
+    }
+
+    // once the distance out from the box exceeds its extent the larger axis
+    // difference is always the outward one
+    fn settled_margin(&self, width : i32, height : i32) -> i32 {
+        width + height + 1
+    }
+}
+
+impl Metric for SquaredEuclidean {
+    fn distance(&self, x1 : (i32, i32), x2 : (i32, i32)) -> i64 {
+        let (dx, dy) = ((x1.0 - x2.0) as i64, (x1.1 - x2.1) as i64);
+        dx * dx + dy * dy
+    }
+
+    fn is_separable(&self) -> bool {
+        true
+    }
+
+    // the difference between two squared distances grows linearly along a
+    // ray, so the owners settle once the linear term outweighs the box
+    fn settled_margin(&self, width : i32, height : i32) -> i32 {
+        width * width + height * height + 1
+    }
 }
 
 fn distance(x1 : (i32, i32), x2 : (i32, i32)) -> i32 {
     (x1.0 - x2.0).abs() + (x1.1 - x2.1).abs()
 }
 
-fn identify_closest_item( target: (i32, i32), values : &Vec<(i32, i32)> ) -> Option<(i32, i32)> {
-    let mut d : Vec<((i32,i32), i32)>= values.iter().map(|x| (*x, distance(target, *x))).collect();
-    d.sort_by(|x,y| x.1.cmp(&y.1));
-    if d.len() >= 2 && d[0].1 == d[1].1 {
-        None
+fn identify_closest_item<M : Metric>( target: (i32, i32), values : &[(i32, i32)], metric : &M ) -> Option<(i32, i32)> {
+    // the nearest so far, and whether another is as near
+    let mut best : Option<((i32,i32), i64, bool)> = None;
+    for x in values {
+        let d = metric.distance(target, *x);
+        match best {
+            Some((_, nearest, _)) if d > nearest => {},
+            Some((v, nearest, _)) if d == nearest => best = Some((v, nearest, true)),
+            _ => best = Some((*x, d, false))
+        }
+    }
+    match best {
+        Some((v, _, false)) => Some(v),
+        _ => None
+    }
+}
+
+fn identify_distance_to_all_coordinates<M : Metric>( target: (i32, i32), values : &[(i32, i32)], metric : &M ) -> i64 {
+    values.iter().fold(0, |sum, x| sum + metric.distance(target, *x))
+}
+
+// counts every cell whose total distance is below the target, wherever it lies.
+// Each distance is at least the difference along an axis, so no cell further than
+// target / count outside the bounding box can be in the region.
+fn region_size_by_distance<M : Metric>(values : &[(i32, i32)], target_distance : i64, metric : &M) -> i64 {
+    let (xmin, xmax, ymin, ymax) = identify_bounds(values);
+    let reach = (target_distance / values.len() as i64) as i32;
+    let (xs, ys) = ((xmin - reach)..=(xmax + reach), (ymin - reach)..=(ymax + reach));
+    if metric.is_separable() {
+        region_size_by_axis_sums(values, target_distance, metric, xs, ys)
     } else {
-        Some(d[0].0)
+        region_size_by_rows(values, target_distance, metric, xs, ys)
     }
 }
 
-fn identify_distance_to_all_coordinates( target: (i32, i32), values : &Vec<(i32, i32)> ) -> i32 {
-    values.iter().fold(0, |sum, x| sum + distance(target, *x))
+// the total distance of a separable metric is a column sum plus a row sum, so
+// sorting the row sums lets each column count its rows with a binary search
+fn region_size_by_axis_sums<M : Metric>(values : &[(i32, i32)], target_distance : i64, metric : &M,
+                                        xs : RangeInclusive<i32>, ys : RangeInclusive<i32>) -> i64 {
+    let column_sums : Vec<i64> = xs.map(|x| values.iter().map(|v| metric.distance((x, 0), (v.0, 0))).sum()).collect();
+    let mut row_sums : Vec<i64> = ys.map(|y| values.iter().map(|v| metric.distance((0, y), (0, v.1))).sum()).collect();
+    row_sums.sort();
+    column_sums.iter()
+               .map(|c| row_sums.partition_point(|r| c + r < target_distance) as i64)
+               .sum()
 }
 
-fn region_size_by_distance(values : &Vec<(i32, i32)>, target_distance : i32) -> i32 {
-    let (xmin, xmax, ymin, ymax) = identify_bounds(&values);
+// the total distance is convex along each row, so the cells within the region
+// form an interval either side of the row's minimum
+fn region_size_by_rows<M : Metric>(values : &[(i32, i32)], target_distance : i64, metric : &M,
+                                   xs : RangeInclusive<i32>, ys : RangeInclusive<i32>) -> i64 {
     let mut region_size = 0;
-    for y in (ymin - 1)..ymax+2 {
-        for x in (xmin-1)..xmax+2 {
-            let dist = identify_distance_to_all_coordinates((x,y), values);
-            if dist < target_distance {
-                region_size = region_size+1;
+    for y in ys {
+        let total = |x| identify_distance_to_all_coordinates((x, y), values, metric);
+        let (mut lo, mut hi) = (*xs.start(), *xs.end());
+        // ternary search for the minimum, equal values bracket it
+        while hi - lo > 2 {
+            let (m1, m2) = (lo + (hi - lo) / 3, hi - (hi - lo) / 3);
+            let (t1, t2) = (total(m1), total(m2));
+            if t1 < t2 {
+                hi = m2;
+            } else if t1 > t2 {
+                lo = m1;
+            } else {
+                lo = m1;
+                hi = m2;
             }
         }
+        let centre = (lo..=hi).min_by_key(|x| total(*x)).unwrap();
+        if total(centre) >= target_distance {
+            continue;
+        }
+        // first cell inside the region on the left, and last one on the right
+        let (mut l, mut r) = (*xs.start(), centre);
+        while l < r {
+            let m = l + (r - l) / 2;
+            if total(m) < target_distance { r = m } else { l = m + 1 }
+        }
+        let left = l;
+        let (mut l, mut r) = (centre, *xs.end());
+        while l < r {
+            let m = l + (r - l + 1) / 2;
+            if total(m) < target_distance { l = m } else { r = m - 1 }
+        }
+        region_size += (l - left + 1) as i64;
     }
     region_size
 }
 
-fn identify_bounds(values : &Vec<(i32,i32)>) -> (i32, i32, i32, i32) {
+fn identify_bounds(values : &[(i32,i32)]) -> (i32, i32, i32, i32) {
     let xs : Vec<i32>= values.iter().map(|(x,_y)| *x).collect();
     let ys : Vec<i32>= values.iter().map(|(_x,y)| *y).collect();
     (*xs.iter().min().unwrap(),
@@ -67,12 +242,77 @@ fn identify_bounds(values : &Vec<(i32,i32)>) -> (i32, i32, i32, i32) {
      *ys.iter().max().unwrap())
 }
 
-fn populate_grid(values : &Vec<(i32,i32)>) -> HashMap<(i32,i32),(i32,i32)> {
-    let (xmin, xmax, ymin, ymax) = identify_bounds(&values);
+// labels the cells out to margin beyond the bounding box with their nearest coordinate
+fn populate_grid<M : Metric>(values : &[(i32,i32)], metric : &M, margin : i32) -> HashMap<(i32,i32),(i32,i32)> {
+    match metric.steps() {
+        Some(steps) => populate_grid_by_fill(values, steps, margin),
+        None => populate_grid_by_search(values, metric, margin)
+    }
+}
+
+// labels every cell by flooding outwards from all the coordinates at once, one
+// distance at a time. The nearest coordinates of a cell are exactly those of its
+// neighbours one step closer, so a cell reached from two different owners in the
+// same round (or from a tied cell) is itself tied.
+fn populate_grid_by_fill(values : &[(i32,i32)], steps : &[(i32, i32)], margin : i32) -> HashMap<(i32,i32),(i32,i32)> {
+    let (xmin, xmax, ymin, ymax) = identify_bounds(values);
+    let (width, height) = (xmax - xmin + 1 + 2 * margin, ymax - ymin + 1 + 2 * margin);
+    let index = |(x, y) : (i32, i32)| ((y - ymin + margin) * width + (x - xmin + margin)) as usize;
+    // distance from the nearest coordinate and its index, None when tied
+    let mut dist = vec![-1; (width * height) as usize];
+    let mut owner : Vec<Option<usize>> = vec![None; (width * height) as usize];
+    let mut frontier = Vec::new();
+    for (i, v) in values.iter().enumerate() {
+        let pos = index(*v);
+        if dist[pos] == 0 {
+            // duplicate coordinates tie with each other
+            owner[pos] = None;
+        } else {
+            dist[pos] = 0;
+            owner[pos] = Some(i);
+            frontier.push(*v);
+        }
+    }
+    let mut d = 0;
+    while !frontier.is_empty() {
+        let mut next = Vec::new();
+        for (x, y) in frontier {
+            let current = owner[index((x, y))];
+            for (dx, dy) in steps {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < xmin - margin || nx > xmax + margin || ny < ymin - margin || ny > ymax + margin {
+                    continue;
+                }
+                let pos = index((nx, ny));
+                if dist[pos] == -1 {
+                    dist[pos] = d + 1;
+                    owner[pos] = current;
+                    next.push((nx, ny));
+                } else if dist[pos] == d + 1 && owner[pos] != current {
+                    owner[pos] = None;
+                }
+            }
+        }
+        frontier = next;
+        d += 1;
+    }
     let mut grid = HashMap::<(i32,i32), (i32,i32)>::new();
-    for y in (ymin - 1)..ymax+2 {
-        for x in (xmin-1)..xmax+2 {
-            match identify_closest_item((x,y), &values) {
+    for y in (ymin - margin)..=(ymax + margin) {
+        for x in (xmin - margin)..=(xmax + margin) {
+            if let Some(i) = owner[index((x, y))] {
+                grid.insert((x, y), values[i]);
+            }
+        }
+    }
+    grid
+}
+
+fn populate_grid_by_search<M : Metric>(values : &[(i32,i32)], metric : &M, margin : i32) -> HashMap<(i32,i32),(i32,i32)> {
+    let (xmin, xmax, ymin, ymax) = identify_bounds(values);
+    let mut grid = HashMap::<(i32,i32), (i32,i32)>::new();
+    for y in (ymin - margin)..=(ymax + margin) {
+        for x in (xmin - margin)..=(xmax + margin) {
+            match identify_closest_item((x,y), values, metric) {
                 Some(entry) => grid.insert( (x,y), entry ),
                 None => None
             };
@@ -81,32 +321,56 @@ fn populate_grid(values : &Vec<(i32,i32)>) -> HashMap<(i32,i32),(i32,i32)> {
     grid
 }
 
-fn identify_infinite(values : &Vec<(i32, i32)>) -> HashSet<(i32,i32)> {
-    let (xmin, xmax, ymin, ymax) = identify_bounds(&values);
-    let grid = populate_grid(&values);
+// a region is infinite when it owns a cell on the ring far enough out that the
+// owner along every outward ray has settled
+fn identify_infinite<M : Metric>(values : &[(i32, i32)], metric : &M) -> HashSet<(i32,i32)> {
+    let (xmin, xmax, ymin, ymax) = identify_bounds(values);
+    owners_on_ring(values, metric.settled_margin(xmax - xmin, ymax - ymin), metric)
+}
+
+fn owners_on_ring<M : Metric>(values : &[(i32, i32)], margin : i32, metric : &M) -> HashSet<(i32,i32)> {
+    let (xmin, xmax, ymin, ymax) = identify_bounds(values);
+    let (left, right, top, bottom) = (xmin - margin, xmax + margin, ymin - margin, ymax + margin);
     let mut infinite = HashSet::<(i32,i32)>::new();
-    for x in xmin-1..xmax+1 {
-        if let Some(entry) = grid.get(&(x,ymin-1)) {
-            infinite.insert(*entry);
-        }
-        if let Some(entry) = grid.get(&(x,ymax+1)) {
-            infinite.insert(*entry);
+    let ring = (left..=right).flat_map(|x| vec![(x, top), (x, bottom)])
+                             .chain((top..=bottom).flat_map(|y| vec![(left, y), (right, y)]));
+    for cell in ring {
+        if let Some(entry) = identify_closest_item(cell, values, metric) {
+            infinite.insert(entry);
         }
     }
-    for y in ymin-1..ymax+1 {
-        if let Some(entry) = grid.get(&(xmin-1,y)) {
-            infinite.insert(*entry);
-        }
-        if let Some(entry) = grid.get(&(xmax+1,y)) {
-            infinite.insert(*entry);
+    infinite
+}
+
+// the most cells largest_finite will label before giving up
+const MAX_LABELLED_CELLS : i64 = 10_000_000;
+
+#[derive(Debug, PartialEq)]
+enum RegionError {
+    // the grid out to the settled margin, too large to label
+    TooFarToLabel { width : i64, height : i64 }
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegionError::TooFarToLabel { width, height } => write!(f, "too far to label ({}x{} cells)", width, height)
         }
     }
-    infinite
 }
 
-fn largest_finite(values : &Vec<(i32,i32)>) -> i32 {
-    let grid = populate_grid(&values);
-    let infinite = identify_infinite(values);
+// a finite region owns nothing on the settled ring, and so nothing beyond it either. For
+// Manhattan that ring is just outside the bounding box, but other metrics can leave
+// finite regions reaching much further out, sometimes too far to label the grid.
+fn largest_finite<M : Metric>(values : &[(i32,i32)], metric : &M) -> Result<i32, RegionError> {
+    let (xmin, xmax, ymin, ymax) = identify_bounds(values);
+    let margin = metric.settled_margin(xmax - xmin, ymax - ymin);
+    let (width, height) = ((xmax - xmin + 1 + 2 * margin) as i64, (ymax - ymin + 1 + 2 * margin) as i64);
+    if width * height > MAX_LABELLED_CELLS {
+        return Err(RegionError::TooFarToLabel { width, height });
+    }
+    let grid = populate_grid(values, metric, margin);
+    let infinite = identify_infinite(values, metric);
     let mut nearest = HashMap::<(i32,i32), i32>::new();
     for (_, neighbour) in grid {
         // don't count entries which  are infinite
@@ -115,7 +379,95 @@ fn largest_finite(values : &Vec<(i32,i32)>) -> i32 {
             *v += 1;
         };
     };
-    *nearest.values().max().unwrap()
+    Ok(*nearest.values().max().unwrap())
+}
+
+// the letter for a coordinate, wrapping round once the alphabet runs out
+fn label(index : usize) -> char {
+    (b'A' + (index % 26) as u8) as char
+}
+
+// draws the grid in the style of the puzzle, with each coordinate as a capital
+// letter, the cells it owns in lowercase and tied cells as '.'
+fn render_map(values : &[(i32, i32)], grid : &HashMap<(i32,i32),(i32,i32)>) -> String {
+    let (xmin, xmax, ymin, ymax) = identify_bounds(values);
+    let labels : HashMap<(i32,i32), char> = values.iter().enumerate().map(|(i, v)| (*v, label(i))).collect();
+    let mut lines = Vec::new();
+    for y in (ymin - 1)..ymax+2 {
+        let line : String = ((xmin - 1)..xmax+2).map(|x| {
+            if let Some(c) = labels.get(&(x, y)) {
+                *c
+            } else {
+                grid.get(&(x, y)).map_or('.', |owner| labels[owner].to_ascii_lowercase())
+            }
+        }).collect();
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+// a binary PPM image of the grid, one pixel per cell. Finite regions are drawn
+// in bright colours and infinite ones darkened, while the cells of the safe
+// region are washed towards white. Coordinates are black and ties grey.
+fn render_ppm<M : Metric>(values : &[(i32, i32)], grid : &HashMap<(i32,i32),(i32,i32)>, metric : &M, target_distance : i64) -> Vec<u8> {
+    let (xmin, xmax, ymin, ymax) = identify_bounds(values);
+    let infinite = identify_infinite(values, metric);
+    let indices : HashMap<(i32,i32), usize> = values.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+    let mut image = format!("P6\n{} {}\n255\n", xmax - xmin + 3, ymax - ymin + 3).into_bytes();
+    for y in (ymin - 1)..ymax+2 {
+        for x in (xmin-1)..xmax+2 {
+            let colour = if indices.contains_key(&(x, y)) {
+                [0, 0, 0]
+            } else {
+                let base = match grid.get(&(x, y)) {
+                    Some(owner) => {
+                        let i = indices[owner];
+                        let colour = [(55 + i * 97 % 200) as u8, (55 + i * 59 % 200) as u8, (55 + i * 31 % 200) as u8];
+                        if infinite.contains(owner) { colour.map(|c| c / 3) } else { colour }
+                    },
+                    None => [128, 128, 128]
+                };
+                if identify_distance_to_all_coordinates((x, y), values, metric) < target_distance {
+                    base.map(|c| ((c as u32 + 255) / 2) as u8)
+                } else {
+                    base
+                }
+            };
+            image.extend_from_slice(&colour);
+        }
+    }
+    image
+}
+
+// pseudo random coordinates within a size x size square, for benchmarking
+fn generate_coordinates(count : usize, size : i32, seed : u64) -> Vec<(i32, i32)> {
+    let mut state = seed;
+    let mut next = || {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % size as u64) as i32
+    };
+    (0..count).map(|_| (next(), next())).collect()
+}
+
+fn bench(count : usize, size : i32) {
+    let values = generate_coordinates(count, size, 0x2545_f491_4f6c_dd1d);
+    println!("Labelling {}x{} grid with {} coordinates", size, size, count);
+    bench_metric("Manhattan", &values, &Manhattan);
+    bench_metric("Chebyshev", &values, &Chebyshev);
+}
+
+fn bench_metric<M : Metric>(name : &str, values : &[(i32, i32)], metric : &M) {
+    let start = Instant::now();
+    let filled = populate_grid_by_fill(values, metric.steps().unwrap(), 1);
+    let fill_time = start.elapsed();
+    let start = Instant::now();
+    let searched = populate_grid_by_search(values, metric, 1);
+    let search_time = start.elapsed();
+    assert_eq!(filled, searched);
+    println!("{} fill {:?} search {:?}", name, fill_time, search_time);
 }
 
 #[cfg(test)]
@@ -127,12 +479,21 @@ mod test {
         assert_eq!(distance((3,2), (4,6)),5);
     }
 
+    #[test]
+    fn test_metrics() {
+        assert_eq!(Manhattan.distance((3,2), (4,6)), 5);
+        assert_eq!(Chebyshev.distance((3,2), (4,6)), 4);
+        assert_eq!(SquaredEuclidean.distance((3,2), (4,6)), 17);
+    }
+
     #[test]
     fn test_identify_closest_item() {
         let values = vec![(1, 1),(1, 6),(8, 3),(3, 4),(5, 5),(8, 9)];
-        assert_eq!(identify_closest_item((4,4),&values), Some((3,4))); 
-        assert_eq!(identify_closest_item((4,8),&values), Some((5,5))); 
-        assert_eq!(identify_closest_item((5,0),&values), None); 
+        assert_eq!(identify_closest_item((4,4),&values,&Manhattan), Some((3,4))); 
+        assert_eq!(identify_closest_item((4,8),&values,&Manhattan), Some((5,5))); 
+        assert_eq!(identify_closest_item((5,0),&values,&Manhattan), None); 
+        assert_eq!(identify_closest_item((5,0),&values,&Chebyshev), Some((8,3))); 
+        assert_eq!(identify_closest_item((4,8),&values,&SquaredEuclidean), Some((5,5))); 
     }
 
     #[test]
@@ -144,11 +505,58 @@ mod test {
     #[test]
     fn test_populate_grid() {
         let values = vec![(1, 1),(1, 6),(8, 3),(3, 4),(5, 5),(8, 9)];
-        let result = populate_grid(&values);        
+        let result = populate_grid(&values, &Manhattan, 1);
         assert_eq!(result.get(&(0,0)), Some(&(1i32,1i32)));
         assert_eq!(result.get(&(7,6)), Some(&(5i32,5i32)));
     }
 
+    #[test]
+    fn test_populate_grid_by_fill() {
+        let values = vec![(1, 1),(1, 6),(8, 3),(3, 4),(5, 5),(8, 9)];
+        assert_eq!(populate_grid_by_fill(&values, Manhattan.steps().unwrap(), 1),
+                   populate_grid_by_search(&values, &Manhattan, 1));
+        let values = generate_coordinates(100, 80, 42);
+        assert_eq!(populate_grid_by_fill(&values, Manhattan.steps().unwrap(), 1),
+                   populate_grid_by_search(&values, &Manhattan, 1));
+        assert_eq!(populate_grid_by_fill(&values, Chebyshev.steps().unwrap(), 1),
+                   populate_grid_by_search(&values, &Chebyshev, 1));
+    }
+
+    #[test]
+    fn test_render_map() {
+        let values = vec![(1, 1),(1, 6),(8, 3),(3, 4),(5, 5),(8, 9)];
+        let map = render_map(&values, &populate_grid(&values, &Manhattan, 1));
+        let lines : Vec<&str> = map.split('\n').collect();
+        assert_eq!(lines[..10].to_vec(), vec![
+            "aaaaa.cccc",
+            "aAaaa.cccc",
+            "aaaddecccc",
+            "aadddeccCc",
+            "..dDdeeccc",
+            "bb.deEeecc",
+            "bBb.eeee..",
+            "bbb.eeefff",
+            "bbb.eeffff",
+            "bbb.ffffFf"
+        ]);
+        assert_eq!(lines.len(), 11);
+    }
+
+    #[test]
+    fn test_render_ppm() {
+        let values = vec![(1, 1),(1, 6),(8, 3),(3, 4),(5, 5),(8, 9)];
+        let image = render_ppm(&values, &populate_grid(&values, &Manhattan, 1), &Manhattan, 32);
+        let header = b"P6\n10 11\n255\n";
+        assert_eq!(&image[..header.len()], header);
+        assert_eq!(image.len(), header.len() + 10 * 11 * 3);
+        let pixel = |x : usize, y : usize| &image[header.len() + (y * 10 + x) * 3..][..3];
+        // coordinate A, a tie, infinite A and the safe region around finite E
+        assert_eq!(pixel(1, 1), [0, 0, 0]);
+        assert_eq!(pixel(5, 0), [128, 128, 128]);
+        assert_eq!(pixel(0, 0), [18, 18, 18]);
+        assert_eq!(pixel(4, 5), [249, 173, 217]);
+    }
+
     #[test]
     fn test_identify_infinite() {
         let values = vec![(1, 1),(1, 6),(8, 3),(3, 4),(5, 5),(8, 9)];
@@ -157,24 +565,86 @@ mod test {
         infinite.insert((1,6));
         infinite.insert((8,3));
         infinite.insert((8,9));
-        assert_eq!(identify_infinite(&values), infinite);
+        assert_eq!(identify_infinite(&values, &Manhattan), infinite);
     }
     
     #[test]
     fn test_identify_largest_finite() {
         let values = vec![(1, 1),(1, 6),(8, 3),(3, 4),(5, 5),(8, 9)];
-        assert_eq!(largest_finite(&values), 17);
+        assert_eq!(largest_finite(&values, &Manhattan), Ok(17));
+    }
+
+    #[test]
+    fn test_largest_finite_beyond_bounds() {
+        // the region of (3,1) reaches three rows below the bounding box
+        let values = vec![(0, 0), (6, 0), (3, 1), (3, 10)];
+        fn scan<M : Metric>(values : &[(i32, i32)], metric : &M) -> i32 {
+            let infinite = identify_infinite(values, metric);
+            let mut counts = HashMap::<(i32,i32), i32>::new();
+            for y in -300..=300 {
+                for x in -300..=300 {
+                    if let Some(owner) = identify_closest_item((x, y), values, metric) {
+                        *counts.entry(owner).or_insert(0) += 1;
+                    }
+                }
+            }
+            counts.iter().filter(|(o, _)| !infinite.contains(o)).map(|(_, c)| *c).max().unwrap()
+        }
+        // 25 of its cells are within one of the bounding box
+        assert_eq!(largest_finite(&values, &SquaredEuclidean), Ok(27));
+        assert_eq!(largest_finite(&values, &SquaredEuclidean), Ok(scan(&values, &SquaredEuclidean)));
+        assert_eq!(largest_finite(&values, &Chebyshev), Ok(scan(&values, &Chebyshev)));
+    }
+
+    #[test]
+    fn test_largest_finite_too_far() {
+        // squared euclidean regions could reach 305*305 + 304*304 + 1 beyond the box
+        let values = vec![(0, 0), (305, 0), (150, 304), (150, 150)];
+        assert_eq!(largest_finite(&values, &SquaredEuclidean),
+                   Err(RegionError::TooFarToLabel { width : 371190, height : 371189 }));
     }
 
     #[test]
     fn test_identify_distance_to_all_coordinates() {
         let values = vec![(1, 1),(1, 6),(8, 3),(3, 4),(5, 5),(8, 9)];
-        assert_eq!(identify_distance_to_all_coordinates((4,3), &values), 30);
+        assert_eq!(identify_distance_to_all_coordinates((4,3), &values, &Manhattan), 30);
     }
 
     #[test]
     fn test_region_size_by_distance() {
         let values = vec![(1, 1),(1, 6),(8, 3),(3, 4),(5, 5),(8, 9)];
-        assert_eq!(region_size_by_distance(&values, 32), 16);
+        assert_eq!(region_size_by_distance(&values, 32, &Manhattan), 16);
+    }
+
+    #[test]
+    fn test_region_size_beyond_bounds() {
+        let values = vec![(1, 1),(1, 6),(8, 3),(3, 4),(5, 5),(8, 9)];
+        fn scan<M : Metric>(values : &[(i32, i32)], target_distance : i64, metric : &M) -> i64 {
+            let mut region_size = 0;
+            for y in -100..110 {
+                for x in -100..110 {
+                    if identify_distance_to_all_coordinates((x,y), values, metric) < target_distance {
+                        region_size += 1;
+                    }
+                }
+            }
+            region_size
+        }
+        assert_eq!(region_size_by_distance(&values, 300, &Manhattan), scan(&values, 300, &Manhattan));
+        assert_eq!(region_size_by_distance(&values, 300, &Chebyshev), scan(&values, 300, &Chebyshev));
+        assert_eq!(region_size_by_distance(&values, 9000, &SquaredEuclidean), scan(&values, 9000, &SquaredEuclidean));
+        assert_eq!(region_size_by_distance(&values, 5, &Manhattan), 0);
+    }
+
+    #[test]
+    fn test_identify_infinite_has_settled() {
+        let values = generate_coordinates(15, 20, 7);
+        let (xmin, xmax, ymin, ymax) = identify_bounds(&values);
+        let (width, height) = (xmax - xmin, ymax - ymin);
+        assert_eq!(identify_infinite(&values, &Manhattan), owners_on_ring(&values, 50, &Manhattan));
+        assert_eq!(identify_infinite(&values, &Chebyshev),
+                   owners_on_ring(&values, 5 * Chebyshev.settled_margin(width, height), &Chebyshev));
+        assert_eq!(identify_infinite(&values, &SquaredEuclidean),
+                   owners_on_ring(&values, 5 * SquaredEuclidean.settled_margin(width, height), &SquaredEuclidean));
     }
 }
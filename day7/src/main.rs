@@ -1,165 +1,840 @@
 extern crate regex;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::env;
+use std::fmt;
 use regex::Regex;
 use std::fs::File;
 use std::io::prelude::*;
 
+// usage: day7 [schedule [csv|svg] | dot | timings | policies] [--durations FILE]
+// where each line of the durations file is a step name and its seconds
 fn main() {
-    println!("Pt1 {}", pt1("input"));
-    println!("Pt2 {}", pt2("input"));
+    let mut args : Vec<String> = env::args().skip(1).collect();
+    let durations = args.iter().position(|a| a == "--durations").map(|i| {
+        args.remove(i);
+        if i < args.len() { args.remove(i) } else { panic!("--durations needs a file") }
+    });
+    if let Err(e) = run(&args, durations.as_deref()) {
+        println!("{}", e);
+    }
+}
+
+fn run(args : &[String], durations : Option<&str>) -> Result<(), GraphError> {
+    let graph = load_graph("input", durations, 60)?;
+    match args.first().map(|a| a.as_str()) {
+        Some("schedule") => {
+            let schedule = multiple_worker_order(&graph, 5);
+            match args.get(1).map_or("table", |a| a.as_str()) {
+                "csv" => print!("{}", schedule.to_csv()),
+                "svg" => print!("{}", schedule.to_svg()),
+                _ => print!("{}", schedule.to_table())
+            }
+        },
+        Some("dot") => print!("{}", to_dot(&graph)),
+        Some("timings") => {
+            let analysis = analyse(&graph);
+            let mut steps : Vec<&String> = analysis.timings.keys().collect();
+            steps.sort();
+            println!("Step  Earliest  Latest  Slack");
+            for step in steps {
+                let timing = &analysis.timings[step];
+                println!("{:>4}  {:>8}  {:>6}  {:>5}", step, timing.earliest_start, timing.latest_start, timing.slack);
+            }
+        },
+        Some("policies") => {
+            let analysis = analyse(&graph);
+            println!("Critical path {} taking {}", analysis.critical_path.join(""), analysis.length);
+            println!("Workers needed {}", workers_for_critical_path(&graph));
+            let workers = vec![Worker::new(); 5];
+            println!("Longest first {}", simulate(&graph, &workers, &LongestFirst)?.total);
+            println!("Most dependents first {}", simulate(&graph, &workers, &MostDependentsFirst)?.total);
+            println!("Critical path first {}", simulate(&graph, &workers, &CriticalPathFirst::new(&graph))?.total);
+            let mut mixed = vec![Worker::new(); 4];
+            mixed.push(Worker { speed : 2.0, skills : None });
+            println!("With one worker twice as fast {}", simulate(&graph, &mixed, &CriticalPathFirst::new(&graph))?.total);
+        },
+        _ => {
+            println!("Pt1 {}", pt1(&graph));
+            println!("Pt2 {}", pt2(&graph));
+        }
+    }
+    Ok(())
 }
 
 fn read_lines_in_file(filename : &str) -> Result<Vec<String>, std::io::Error> {
     let mut f = File::open(filename).expect("file not found");
     let mut contents = String::new();
-    f.read_to_string(&mut contents);
-    let lines = contents.to_string()
-                    .split('\n')
-                    .filter(|s| s.to_string() != "")
+    f.read_to_string(&mut contents)?;
+    let lines = contents.split('\n')
+                    .filter(|s| !s.is_empty())
                     .map(|s| s.to_owned())
                     .collect();
     Ok(lines)
 }
 
-fn parse_line( s : &str ) -> Option<(char, char)> {
-    let line_regex = Regex::new(r"Step (\w) must be finished before step (\w) can begin").unwrap();
-    match line_regex.captures(&s) {
+fn parse_line( s : &str ) -> Option<(String, String)> {
+    let line_regex = Regex::new(r"Step (\w+) must be finished before step (\w+) can begin").unwrap();
+    match line_regex.captures(s) {
         Some(capture) => {
             let s1 = capture.at(1).unwrap();
             let s2 = capture.at(2).unwrap();
-            Some((s1.chars().next().unwrap(), s2.chars().next().unwrap()))
+            Some((s1.to_string(), s2.to_string()))
         },
         None =>  None
     }
 }
 
-fn pt1(filename : &str) -> String {
-    let contents = parse_file(filename);
-    order(group_by_prerecs(contents))
+fn pt1(graph : &TaskGraph) -> String {
+    order(graph).join("")
 }
 
-fn pt2(filename : &str) -> u32 {
-    let contents = parse_file(filename);
-    let (result, t) = multiple_worker_order(group_by_prerecs(contents), 5, 60);
-    t
+fn pt2(graph : &TaskGraph) -> u32 {
+    multiple_worker_order(graph, 5).total
 }
 
-fn parse_file(filename : &str) -> Vec<(char,char)> {
+// the steps from the file, timed from the durations file if given or else by puzzle_graph
+fn load_graph(filename : &str, durations : Option<&str>, base_delay : u32) -> Result<TaskGraph, GraphError> {
+    let entries = parse_file(filename);
+    match durations {
+        Some(durations) => TaskGraph::new(group_by_prerecs(entries), parse_durations(&read_lines_in_file(durations).unwrap())?),
+        None => puzzle_graph(entries, base_delay)
+    }
+}
+
+// lines of a step name followed by its duration in seconds, such as "fetch 5"
+fn parse_durations(lines : &[String]) -> Result<HashMap<String, u32>, GraphError> {
+    lines.iter().map(|line| {
+        match line.split_whitespace().collect::<Vec<&str>>()[..] {
+            [step, seconds] => seconds.parse().map(|d| (step.to_string(), d)).map_err(|_| GraphError::InvalidDuration(line.clone())),
+            _ => Err(GraphError::InvalidDuration(line.clone()))
+        }
+    }).collect()
+}
+
+fn parse_file(filename : &str) -> Vec<(String, String)> {
     read_lines_in_file(filename).unwrap().iter().map(|s| parse_line(s).unwrap()).collect()
 }
 
-fn group_by_prerecs(entries : Vec<(char, char)>) -> HashMap<char, Vec<char>> {
-    let mut prerecs = HashMap::<char, Vec<char>>::new();
-    let mut it = entries.iter();
-    while let Some((pre, target)) = it.next() {
-        prerecs.entry(*target).or_insert(vec![]).push(*pre)
+// builds the graph for the puzzle input, where each step is a capital letter
+// taking base_delay plus its position in the alphabet seconds
+fn puzzle_graph(entries : Vec<(String, String)>, base_delay : u32) -> Result<TaskGraph, GraphError> {
+    let prerecs = group_by_prerecs(entries);
+    let mut durations = HashMap::new();
+    let mut steps : Vec<&String> = prerecs.keys().collect();
+    steps.sort();
+    for step in steps {
+        match step.as_bytes() {
+            [c @ b'A'..=b'Z'] => durations.insert(step.clone(), base_delay + 1 + (c - b'A') as u32),
+            _ => return Err(GraphError::MissingDuration(step.clone()))
+        };
+    }
+    TaskGraph::new(prerecs, durations)
+}
+
+fn group_by_prerecs(entries : Vec<(String, String)>) -> HashMap<String, Vec<String>> {
+    let mut prerecs = HashMap::<String, Vec<String>>::new();
+    for (pre, target) in &entries {
+        prerecs.entry(target.clone()).or_default().push(pre.clone())
     }
     // add entries with no pre-requisities
-    let unavailable : Vec<char> = prerecs.keys().map(|c| *c).collect();
-    let available : Vec<char> = entries.iter().filter(|e| !unavailable.contains(&e.0)).map(|e| e.0).collect();
-    for a in available {
-        prerecs.insert(a, vec![]);
-    };
+    for (pre, _) in entries {
+        prerecs.entry(pre).or_default();
+    }
     prerecs
 }
 
-fn order(prerecs : HashMap<char, Vec<char>>) -> String {
-    order_recur(prerecs, vec![], vec![], String::new())
+#[derive(Debug, PartialEq)]
+enum GraphError {
+    UnknownStep { step : String, prerequisite : String },
+    MissingDuration(String),
+    // a line of a durations file that isn't a step and a number of seconds
+    InvalidDuration(String),
+    NoCapableWorker(String),
+    // steps still waiting on their prerequisites when everything else finished
+    Unscheduled(Vec<String>),
+    // the steps making up the cycle, each one a prerequisite of the next
+    Cycle(Vec<String>)
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::UnknownStep { step, prerequisite } => write!(f, "step {} requires unknown step {}", step, prerequisite),
+            GraphError::MissingDuration(step) => write!(f, "step {} has no duration", step),
+            GraphError::InvalidDuration(line) => write!(f, "expected a step and its seconds, found {:?}", line),
+            GraphError::NoCapableWorker(step) => write!(f, "no worker can take step {}", step),
+            GraphError::Unscheduled(steps) => write!(f, "steps never became available: {}", steps.join(", ")),
+            GraphError::Cycle(steps) => write!(f, "steps form a cycle: {} -> {}", steps.join(" -> "), steps[0])
+        }
+    }
+}
+
+// a set of named steps, the steps each one requires to be finished first
+// and how long each one takes. Steps can optionally be given a kind, which
+// limits the workers able to take them.
+#[derive(Debug)]
+struct TaskGraph {
+    prerecs : HashMap<String, Vec<String>>,
+    durations : HashMap<String, u32>,
+    kinds : HashMap<String, String>
+}
+
+impl TaskGraph {
+    fn new(mut prerecs : HashMap<String, Vec<String>>, durations : HashMap<String, u32>) -> Result<TaskGraph, GraphError> {
+        // a prerequisite listed twice is still only waited on once
+        for v in prerecs.values_mut() {
+            v.sort();
+            v.dedup();
+        }
+        let mut steps : Vec<&String> = prerecs.keys().collect();
+        steps.sort();
+        for step in steps {
+            if let Some(prerequisite) = prerecs[step].iter().find(|p| !prerecs.contains_key(*p)) {
+                return Err(GraphError::UnknownStep { step : step.clone(), prerequisite : prerequisite.clone() });
+            }
+            if !durations.contains_key(step) {
+                return Err(GraphError::MissingDuration(step.clone()));
+            }
+        }
+        let graph = TaskGraph { prerecs, durations, kinds : HashMap::new() };
+        match graph.find_cycle() {
+            Some(cycle) => Err(GraphError::Cycle(cycle)),
+            None => Ok(graph)
+        }
+    }
+
+    fn duration(&self, step : &str) -> u32 {
+        self.durations[step]
+    }
+
+    // steps which require the given step to be finished first
+    fn dependents(&self, step : &str) -> Vec<&String> {
+        let mut dependents : Vec<&String> = self.prerecs.iter()
+                                                .filter(|(_, v)| v.iter().any(|p| p == step))
+                                                .map(|(k, _)| k).collect();
+        dependents.sort();
+        dependents
+    }
+
+    // walks back through the prerequisites of the steps which can never be
+    // unlocked until one repeats
+    fn find_cycle(&self) -> Option<Vec<String>> {
+        let mut unlocked = HashSet::<&String>::new();
+        let mut blocked : BTreeSet<&String> = self.prerecs.keys().collect();
+        while let Some(step) = blocked.iter().find(|s| self.prerecs[**s].iter().all(|p| unlocked.contains(p))).cloned() {
+            blocked.remove(step);
+            unlocked.insert(step);
+        }
+        let mut path : Vec<&String> = vec![*blocked.iter().next()?];
+        loop {
+            let last = path[path.len() - 1];
+            let next = self.prerecs[last].iter().find(|p| blocked.contains(p)).unwrap();
+            if let Some(start) = path.iter().position(|s| *s == next) {
+                let mut cycle : Vec<String> = path[start..].iter().map(|s| s.to_string()).collect();
+                cycle.reverse();
+                // start from the first step alphabetically so the report is stable
+                let first = (0..cycle.len()).min_by_key(|i| &cycle[*i]).unwrap();
+                cycle.rotate_left(first);
+                return Some(cycle);
+            }
+            path.push(next);
+        }
+    }
+}
+
+// completes the available steps one at a time in alphabetical order
+fn order(graph : &TaskGraph) -> Vec<String> {
+    let mut remaining = graph.prerecs.clone();
+    let mut done : Vec<String> = vec![];
+    let mut available = BTreeSet::<String>::new();
+    loop {
+        let newly_unlocked : Vec<String> = remaining.iter()
+                                                    .filter(|(_,v)| v.iter().all(|c| done.contains(c)))
+                                                    .map(|(k,_)| k.clone()).collect();
+        for a in newly_unlocked {
+            remaining.remove(&a);
+            available.insert(a);
+        }
+        match available.iter().next().cloned() {
+            Some(step) => {
+                available.remove(&step);
+                done.push(step);
+            },
+            None => return done
+        }
+    }
 }
 
-fn order_recur(mut prerecs : HashMap<char, Vec<char>>, mut unlocked : Vec<char>, mut available : Vec<char>, mut current : String) -> String {
-    let newly_unlocked : Vec<char> = prerecs.iter()
-                                            .filter(|(_,v)| v.iter().all(|c| unlocked.contains(c)))
-                                            .map(|(k,_)| *k).collect();
+// a step being worked on from its start second until, but not including, its end
+#[derive(Debug, PartialEq)]
+struct Assignment {
+    worker : usize,
+    step : String,
+    start : u32,
+    end : u32
+}
 
-    for a in &newly_unlocked {
-        prerecs.remove(&a);
-        available.push(*a);
-    }        
-    available.sort();
-    
-    if available.len() > 0 {
-        let (head, tail) = available.split_at(1);
-        let c = head.iter().next().unwrap();
-        let mut updated = current.clone();
-        updated.push(*c);
-        unlocked.push(*c);
-        return order_recur(prerecs, unlocked, tail.to_vec(), updated)
+// who worked on what and when, along with the order the steps completed in
+#[derive(Debug)]
+struct Schedule {
+    worker_count : usize,
+    order : Vec<String>,
+    assignments : Vec<Assignment>,
+    total : u32
+}
+
+impl Schedule {
+    // the stretches of time each worker spends waiting, as (worker, start, end)
+    fn idle(&self) -> Vec<(usize, u32, u32)> {
+        let mut idle = vec![];
+        for worker in 0..self.worker_count {
+            let mut busy : Vec<&Assignment> = self.assignments.iter().filter(|a| a.worker == worker).collect();
+            busy.sort_by_key(|a| a.start);
+            let mut free_from = 0;
+            for a in busy {
+                if a.start > free_from {
+                    idle.push((worker, free_from, a.start));
+                }
+                free_from = a.end;
+            }
+            if free_from < self.total {
+                idle.push((worker, free_from, self.total));
+            }
+        }
+        idle
+    }
+
+    // the second by second table from the puzzle, with a row per second up to
+    // the one where everything is done
+    fn to_table(&self) -> String {
+        let width = self.order.iter().map(|s| s.len()).max().unwrap_or(0).max(8);
+        let separator = if self.order.iter().all(|s| s.len() == 1) { "" } else { " " };
+        let mut lines = vec![];
+        let mut header = "Second".to_string();
+        for worker in 0..self.worker_count {
+            header.push_str(&format!("   {:^width$}", format!("Worker {}", worker + 1), width = width));
+        }
+        header.push_str("   Done");
+        lines.push(header);
+        for second in 0..=self.total {
+            let mut line = format!("{:>4}  ", second);
+            for worker in 0..self.worker_count {
+                let step = self.assignments.iter()
+                                           .find(|a| a.worker == worker && a.start <= second && second < a.end)
+                                           .map_or(".", |a| a.step.as_str());
+                line.push_str(&format!("   {:^width$}", step, width = width));
+            }
+            let done : Vec<&str> = self.order.iter()
+                                             .filter(|s| self.assignments.iter().any(|a| a.step == **s && a.end <= second))
+                                             .map(|s| s.as_str()).collect();
+            line.push_str(&format!("   {}", done.join(separator)));
+            lines.push(line.trim_end().to_string());
+        }
+        lines.join("\n") + "\n"
+    }
+
+    fn to_csv(&self) -> String {
+        let mut rows = vec![];
+        for a in &self.assignments {
+            rows.push((a.worker, a.start, format!("{},work,{},{},{}", a.worker + 1, a.step, a.start, a.end)));
+        }
+        for (worker, start, end) in self.idle() {
+            rows.push((worker, start, format!("{},idle,,{},{}", worker + 1, start, end)));
+        }
+        rows.sort();
+        let mut csv = "worker,kind,step,start,end\n".to_string();
+        for (_, _, row) in rows {
+            csv.push_str(&row);
+            csv.push('\n');
+        }
+        csv
+    }
+
+    // a Gantt chart with a lane per worker, scaled to fit roughly 1000 pixels
+    fn to_svg(&self) -> String {
+        let (margin, lane, chart_width) = (80.0, 30.0, 1000.0);
+        let scale = chart_width / self.total.max(1) as f64;
+        let height = lane * self.worker_count as f64 + 40.0;
+        let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"sans-serif\" font-size=\"12\">\n",
+                              margin + chart_width + 20.0, height);
+        for worker in 0..self.worker_count {
+            let y = lane * worker as f64;
+            svg.push_str(&format!("  <text x=\"4\" y=\"{}\">Worker {}</text>\n", y + lane / 2.0 + 4.0, worker + 1));
+        }
+        for (worker, start, end) in self.idle() {
+            svg.push_str(&format!("  <rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" fill=\"#eee\"><title>idle {}-{}</title></rect>\n",
+                                  margin + start as f64 * scale, lane * worker as f64 + 4.0, (end - start) as f64 * scale, lane - 8.0, start, end));
+        }
+        for a in &self.assignments {
+            let (x, y, w) = (margin + a.start as f64 * scale, lane * a.worker as f64 + 4.0, (a.end - a.start) as f64 * scale);
+            svg.push_str(&format!("  <rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" fill=\"#7aa6d6\" stroke=\"#fff\"><title>{} {}-{}</title></rect>\n",
+                                  x, y, w, lane - 8.0, a.step, a.start, a.end));
+            svg.push_str(&format!("  <text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n", x + w / 2.0, y + lane / 2.0, a.step));
+        }
+        let axis = lane * self.worker_count as f64 + 10.0;
+        svg.push_str(&format!("  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#000\"/>\n", margin, axis, margin + chart_width, axis));
+        for tick in 0..=10 {
+            let second = self.total * tick / 10;
+            svg.push_str(&format!("  <text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n", margin + second as f64 * scale, axis + 16.0, second));
+        }
+        svg.push_str("</svg>\n");
+        svg
     }
-    current
 }
 
-fn multiple_worker_order(prerecs : HashMap<char, Vec<char>>, worker_count : usize, base_delay : u32) -> (String, u32) {
-    multiple_worker_order_recur(prerecs, worker_count, vec![], base_delay, vec![], vec![], String::new(), 0)
+#[derive(Debug, Clone)]
+struct Worker {
+    // how many times faster than the step durations this worker is
+    speed : f64,
+    // the kinds of step this worker can take, or any step when None
+    skills : Option<Vec<String>>
 }
 
-fn multiple_worker_order_recur(mut prerecs : HashMap<char, Vec<char>>, 
-                               worker_count : usize,
-                               mut workers: Vec<(char, u32)>, 
-                               base_delay : u32,
-                               mut unlocked : Vec<char>, 
-                               mut available : Vec<char>, 
-                               current : String,
-                               now : u32) -> (String, u32) {
-    let newly_unlocked : Vec<char> = prerecs.iter()
-                                            .filter(|(_,v)| v.iter().all(|c| unlocked.contains(c)))
-                                            .map(|(k,_)| *k).collect();
+impl Worker {
+    fn new() -> Worker {
+        Worker { speed : 1.0, skills : None }
+    }
 
-    for a in &newly_unlocked {
-        prerecs.remove(&a);
-        available.push(*a);
-    }        
-    available.sort();
+    fn can_take(&self, graph : &TaskGraph, step : &str) -> bool {
+        match (&self.skills, graph.kinds.get(step)) {
+            (None, _) => true,
+            (Some(skills), Some(kind)) => skills.contains(kind),
+            (Some(_), None) => false
+        }
+    }
 
-    while workers.len() < worker_count && available.len() > 0 {
-        let c = available.remove(0);
-        let delay : u32 = (c as u8 - 'A' as u8) as u32;
-        workers.push((c, now + 1 + base_delay + delay));
+    fn time_for(&self, duration : u32) -> u32 {
+        (duration as f64 / self.speed).ceil() as u32
     }
+}
 
-    if workers.len() > 0 {
-        workers.sort_by(|x,y| x.1.cmp(&y.1));
-        let (c, time) =  workers.remove(0);
-        let mut updated = current.clone();
-        updated.push(c);
-        unlocked.push(c);
-        return multiple_worker_order_recur(prerecs, worker_count, workers, base_delay, unlocked, available, updated, time)
+// decides which of the available steps is started first
+trait Policy {
+    // higher ranks are picked first, with ties going to the alphabetically first step
+    fn rank(&self, graph : &TaskGraph, step : &str) -> u32;
+}
+
+struct Alphabetical;
+struct LongestFirst;
+struct MostDependentsFirst;
+
+// prefers the step with the longest chain of work still to come after it starts
+struct CriticalPathFirst {
+    remaining : HashMap<String, u32>
+}
+
+impl Policy for Alphabetical {
+    fn rank(&self, _graph : &TaskGraph, _step : &str) -> u32 {
+        0
+    }
+}
+
+impl Policy for LongestFirst {
+    fn rank(&self, graph : &TaskGraph, step : &str) -> u32 {
+        graph.duration(step)
+    }
+}
+
+impl Policy for MostDependentsFirst {
+    fn rank(&self, graph : &TaskGraph, step : &str) -> u32 {
+        graph.dependents(step).len() as u32
+    }
+}
+
+impl CriticalPathFirst {
+    fn new(graph : &TaskGraph) -> CriticalPathFirst {
+        let analysis = analyse(graph);
+        let remaining = analysis.timings.iter().map(|(s, t)| (s.clone(), analysis.length - t.latest_start)).collect();
+        CriticalPathFirst { remaining }
+    }
+}
+
+impl Policy for CriticalPathFirst {
+    fn rank(&self, _graph : &TaskGraph, step : &str) -> u32 {
+        self.remaining[step]
     }
-    (current, now)
 }
 
+fn multiple_worker_order(graph : &TaskGraph, worker_count : usize) -> Schedule {
+    simulate(graph, &vec![Worker::new(); worker_count], &Alphabetical).unwrap()
+}
 
+// runs the workers second by second from one step finishing to the next. Each
+// time a step finishes the highest ranked available steps are handed to the
+// fastest free workers able to take them.
+fn simulate<P : Policy>(graph : &TaskGraph, workers : &[Worker], policy : &P) -> Result<Schedule, GraphError> {
+    let mut steps : Vec<&String> = graph.prerecs.keys().collect();
+    steps.sort();
+    if let Some(step) = steps.iter().find(|s| !workers.iter().any(|w| w.can_take(graph, s))) {
+        return Err(GraphError::NoCapableWorker(step.to_string()));
+    }
+    let mut schedule = Schedule { worker_count : workers.len(), order : vec![], assignments : vec![], total : 0 };
+    let mut waiting_on : HashMap<&str, usize> = graph.prerecs.iter().map(|(k, v)| (k.as_str(), v.len())).collect();
+    let mut available = BinaryHeap::new();
+    for step in steps.iter().filter(|s| graph.prerecs[**s].is_empty()) {
+        available.push((policy.rank(graph, step), Reverse(step.to_string())));
+    }
+    let mut free = vec![true; workers.len()];
+    // in progress steps as (finish, start order, worker, step)
+    let mut running = BinaryHeap::<Reverse<(u32, usize, usize, String)>>::new();
+    let mut now = 0;
+    loop {
+        let mut skipped = vec![];
+        while free.contains(&true) {
+            let (rank, Reverse(step)) = match available.pop() {
+                Some(entry) => entry,
+                None => break
+            };
+            let worker = (0..workers.len()).filter(|w| free[*w] && workers[*w].can_take(graph, &step))
+                                           .min_by(|a, b| workers[*b].speed.partial_cmp(&workers[*a].speed).unwrap().then(a.cmp(b)));
+            match worker {
+                Some(worker) => {
+                    let end = now + workers[worker].time_for(graph.duration(&step));
+                    free[worker] = false;
+                    running.push(Reverse((end, schedule.assignments.len(), worker, step.clone())));
+                    schedule.assignments.push(Assignment { worker, step, start : now, end });
+                },
+                None => skipped.push((rank, Reverse(step)))
+            }
+        }
+        available.extend(skipped);
+
+        now = match running.peek() {
+            Some(Reverse((end, _, _, _))) => *end,
+            None => break
+        };
+        // finish everything ending this second before handing out more work
+        while running.peek().is_some_and(|Reverse((end, _, _, _))| *end == now) {
+            let Reverse((_, _, worker, step)) = running.pop().unwrap();
+            free[worker] = true;
+            for dependent in graph.dependents(&step) {
+                let count = waiting_on.get_mut(dependent.as_str()).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    available.push((policy.rank(graph, dependent), Reverse(dependent.clone())));
+                }
+            }
+            schedule.order.push(step);
+        }
+    }
+    if schedule.order.len() < steps.len() {
+        let unscheduled = steps.iter().filter(|s| !schedule.order.contains(s)).map(|s| s.to_string()).collect();
+        return Err(GraphError::Unscheduled(unscheduled));
+    }
+    schedule.total = now;
+    Ok(schedule)
+}
+
+// when a step could start given unlimited workers, and how late it could start
+// without delaying the whole graph
+#[derive(Debug, PartialEq)]
+struct StepTiming {
+    earliest_start : u32,
+    latest_start : u32,
+    slack : u32
+}
+
+#[derive(Debug)]
+struct Analysis {
+    timings : HashMap<String, StepTiming>,
+    // the chain of steps with no slack, in the order they run
+    critical_path : Vec<String>,
+    length : u32
+}
+
+fn analyse(graph : &TaskGraph) -> Analysis {
+    let steps = order(graph);
+    let mut earliest = HashMap::<&str, u32>::new();
+    for step in &steps {
+        let start = graph.prerecs[step].iter().map(|p| earliest[p.as_str()] + graph.duration(p)).max().unwrap_or(0);
+        earliest.insert(step, start);
+    }
+    let length = steps.iter().map(|s| earliest[s.as_str()] + graph.duration(s)).max().unwrap_or(0);
+    let mut latest = HashMap::<&str, u32>::new();
+    for step in steps.iter().rev() {
+        let finish = graph.dependents(step).iter().map(|d| latest[d.as_str()]).min().unwrap_or(length);
+        latest.insert(step, finish - graph.duration(step));
+    }
+    let timings : HashMap<String, StepTiming> = steps.iter().map(|s| {
+        let (earliest_start, latest_start) = (earliest[s.as_str()], latest[s.as_str()]);
+        (s.clone(), StepTiming { earliest_start, latest_start, slack : latest_start - earliest_start })
+    }).collect();
+
+    // follow the steps with no slack which start as soon as the previous one ends
+    let mut critical_path = vec![];
+    let mut next = steps.iter().filter(|s| timings[*s].slack == 0 && timings[*s].earliest_start == 0).min();
+    while let Some(step) = next {
+        critical_path.push(step.clone());
+        let finish = timings[step].earliest_start + graph.duration(step);
+        next = graph.dependents(step).into_iter().find(|d| timings[*d].slack == 0 && timings[*d].earliest_start == finish);
+    }
+    Analysis { timings, critical_path, length }
+}
+
+// the fewest workers for which any of the policies finishes in the critical path
+// time. Scheduling anomalies mean more workers is not always faster, so each
+// count is tried in turn from the lower bound given by the total work.
+fn workers_for_critical_path(graph : &TaskGraph) -> usize {
+    let length = analyse(graph).length;
+    let work : u32 = graph.durations.values().sum();
+    let lower = if length == 0 { 1 } else { work.div_ceil(length) as usize };
+    let critical_path_first = CriticalPathFirst::new(graph);
+    let finishes = |count : usize| {
+        let workers = vec![Worker::new(); count];
+        [simulate(graph, &workers, &Alphabetical),
+         simulate(graph, &workers, &LongestFirst),
+         simulate(graph, &workers, &MostDependentsFirst),
+         simulate(graph, &workers, &critical_path_first)].iter().any(|s| s.as_ref().unwrap().total == length)
+    };
+    (lower.max(1)..=graph.prerecs.len().max(1)).find(|w| finishes(*w)).unwrap()
+}
+
+// a Graphviz description of the graph, with each step labelled with its duration
+// and its position in the single worker order, and the critical path in red
+fn to_dot(graph : &TaskGraph) -> String {
+    let steps = order(graph);
+    let critical_path = analyse(graph).critical_path;
+    let on_path = |step : &str| critical_path.iter().any(|s| s == step);
+    let mut dot = "digraph steps {\n    rankdir=LR;\n    node [shape=box];\n".to_string();
+    for (position, step) in steps.iter().enumerate() {
+        let highlight = if on_path(step) { ", color=red, penwidth=2" } else { "" };
+        dot.push_str(&format!("    \"{}\" [label=\"{}\\n{}s #{}\"{}];\n", step, step, graph.duration(step), position + 1, highlight));
+    }
+    for step in &steps {
+        let mut prerecs = graph.prerecs[step].clone();
+        prerecs.sort();
+        for pre in prerecs {
+            let consecutive = critical_path.windows(2).any(|w| w[0] == pre && w[1] == *step);
+            let highlight = if consecutive { " [color=red, penwidth=2]" } else { "" };
+            dot.push_str(&format!("    \"{}\" -> \"{}\"{};\n", pre, step, highlight));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
 
 #[cfg(test)]
 mod test {
 
     use super::*;
 
+    fn entries(pairs : &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect()
+    }
+
+    fn example() -> Vec<(String, String)> {
+        entries(&[("C","A"), ("C","F"), ("A","B"),("A","D"),("B","E"),("D","E"),("F","E")])
+    }
+
     #[test]
     fn test_read_lines() {
-        assert_eq!(parse_file("input.txt"), vec![('C','A'), ('C','F'), ('A','B'),('A','D'),('B','E'),('D','E'),('F','E')]);
+        assert_eq!(parse_file("input.txt"), example());
+    }
+
+    #[test]
+    fn test_parse_named_steps() {
+        assert_eq!(parse_line("Step fetch must be finished before step build2 can begin."),
+                   Some(("fetch".to_string(), "build2".to_string())));
+        assert_eq!(parse_line("Step fetch finishes"), None);
     }
 
     #[test]
     fn test_group_by_prerecs() {
-        let entries = vec![('C','A'), ('C','F'), ('A','B'),('A','D'),('B','E'),('D','E'),('F','E')];
-        let result : HashMap<char, Vec<char>> = group_by_prerecs(entries);
+        let result : HashMap<String, Vec<String>> = group_by_prerecs(example());
         assert_eq!(result.len(), 6);
-        assert_eq!(result.get(&'C'), Some(&vec![]));
-        assert_eq!(result.get(&'E'), Some(&vec!['B','D','F']));        
+        assert_eq!(result.get("C"), Some(&vec![]));
+        assert_eq!(result.get("E"), Some(&vec!["B".to_string(),"D".to_string(),"F".to_string()]));
     }
 
     #[test]
     fn test_order() {
-        let entries = vec![('C','A'), ('C','F'), ('A','B'),('A','D'),('B','E'),('D','E'),('F','E')];
-        assert_eq!(order(group_by_prerecs(entries)), "CABDFE");
+        assert_eq!(order(&puzzle_graph(example(), 0).unwrap()).join(""), "CABDFE");
     }
 
     #[test]
     fn test_multi_worker_order() {
-        let entries = vec![('C','A'), ('C','F'), ('A','B'),('A','D'),('B','E'),('D','E'),('F','E')];
-        let (result, time) = multiple_worker_order(group_by_prerecs(entries), 2, 0);
-        assert_eq!(result, "CABFDE");
-        assert_eq!(time, 15);        
+        let schedule = multiple_worker_order(&puzzle_graph(example(), 0).unwrap(), 2);
+        assert_eq!(schedule.order.join(""), "CABFDE");
+        assert_eq!(schedule.total, 15);
+    }
+
+    #[test]
+    fn test_schedule() {
+        let schedule = multiple_worker_order(&puzzle_graph(example(), 0).unwrap(), 2);
+        assert_eq!(schedule.assignments[0], Assignment { worker : 0, step : "C".to_string(), start : 0, end : 3 });
+        assert_eq!(schedule.assignments[2], Assignment { worker : 1, step : "F".to_string(), start : 3, end : 9 });
+        assert_eq!(schedule.idle(), vec![(1, 0, 3), (1, 9, 15)]);
+        assert_eq!(schedule.to_table(), "\
+Second   Worker 1   Worker 2   Done
+   0        C          .
+   1        C          .
+   2        C          .
+   3        A          F       C
+   4        B          F       CA
+   5        B          F       CA
+   6        D          F       CAB
+   7        D          F       CAB
+   8        D          F       CAB
+   9        D          .       CABF
+  10        E          .       CABFD
+  11        E          .       CABFD
+  12        E          .       CABFD
+  13        E          .       CABFD
+  14        E          .       CABFD
+  15        .          .       CABFDE
+");
+        let csv = schedule.to_csv();
+        assert_eq!(csv.lines().take(4).collect::<Vec<&str>>(),
+                   vec!["worker,kind,step,start,end", "1,work,C,0,3", "1,work,A,3,4", "1,work,B,4,6"]);
+        assert!(csv.contains("2,idle,,0,3\n"));
+        let svg = schedule.to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("<title>F 3-9</title>"));
+        assert!(svg.contains("<title>idle 9-15</title>"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_analyse() {
+        let graph = puzzle_graph(example(), 0).unwrap();
+        let analysis = analyse(&graph);
+        assert_eq!(analysis.length, 14);
+        assert_eq!(analysis.critical_path, vec!["C", "F", "E"]);
+        assert_eq!(analysis.timings["A"], StepTiming { earliest_start : 3, latest_start : 4, slack : 1 });
+        assert_eq!(analysis.timings["B"], StepTiming { earliest_start : 4, latest_start : 7, slack : 3 });
+        assert_eq!(analysis.timings["E"], StepTiming { earliest_start : 9, latest_start : 9, slack : 0 });
+        assert_eq!(graph.dependents("A"), vec!["B", "D"]);
+        assert_eq!(workers_for_critical_path(&graph), 3);
+    }
+
+    #[test]
+    fn test_policies() {
+        let graph = puzzle_graph(example(), 0).unwrap();
+        let workers = vec![Worker::new(); 2];
+        assert_eq!(simulate(&graph, &workers, &Alphabetical).unwrap().order.join(""), "CABFDE");
+        let schedule = simulate(&graph, &workers, &LongestFirst).unwrap();
+        assert_eq!(schedule.order.join(""), "CADFBE");
+        assert_eq!(schedule.total, 15);
+        assert_eq!(simulate(&graph, &workers, &MostDependentsFirst).unwrap().order.join(""), "CABFDE");
+        let schedule = simulate(&graph, &workers, &CriticalPathFirst::new(&graph)).unwrap();
+        assert_eq!(schedule.order.join(""), "CADFBE");
+        assert_eq!(schedule.assignments[3], Assignment { worker : 1, step : "D".to_string(), start : 4, end : 8 });
+    }
+
+    #[test]
+    fn test_heterogeneous_workers() {
+        let mut graph = puzzle_graph(example(), 0).unwrap();
+        let workers = vec![Worker::new(), Worker { speed : 2.0, skills : None }];
+        let schedule = simulate(&graph, &workers, &Alphabetical).unwrap();
+        // the fast worker takes C in 2 seconds rather than 3
+        assert_eq!(schedule.assignments[0], Assignment { worker : 1, step : "C".to_string(), start : 0, end : 2 });
+
+        graph.kinds.insert("E".to_string(), "deploy".to_string());
+        let workers = vec![Worker { speed : 1.0, skills : Some(vec!["deploy".to_string()]) }, Worker::new()];
+        let schedule = simulate(&graph, &workers, &Alphabetical).unwrap();
+        assert!(schedule.assignments.iter().all(|a| (a.worker == 0) == (a.step == "E")));
+        assert_eq!(schedule.total, 21);
+
+        let workers = vec![Worker { speed : 1.0, skills : Some(vec!["build".to_string()]) }];
+        assert_eq!(simulate(&graph, &workers, &Alphabetical).unwrap_err(), GraphError::NoCapableWorker("A".to_string()));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        assert_eq!(to_dot(&puzzle_graph(example(), 0).unwrap()), "\
+digraph steps {
+    rankdir=LR;
+    node [shape=box];
+    \"C\" [label=\"C\\n3s #1\", color=red, penwidth=2];
+    \"A\" [label=\"A\\n1s #2\"];
+    \"B\" [label=\"B\\n2s #3\"];
+    \"D\" [label=\"D\\n4s #4\"];
+    \"F\" [label=\"F\\n6s #5\", color=red, penwidth=2];
+    \"E\" [label=\"E\\n5s #6\", color=red, penwidth=2];
+    \"C\" -> \"A\";
+    \"A\" -> \"B\";
+    \"A\" -> \"D\";
+    \"C\" -> \"F\" [color=red, penwidth=2];
+    \"B\" -> \"E\";
+    \"D\" -> \"E\";
+    \"F\" -> \"E\" [color=red, penwidth=2];
+}
+");
+    }
+
+    #[test]
+    fn test_explicit_durations() {
+        let steps = entries(&[("fetch","build"), ("build","test"), ("build","package")]);
+        assert_eq!(puzzle_graph(steps.clone(), 0).unwrap_err(), GraphError::MissingDuration("build".to_string()));
+        let lines : Vec<String> = ["fetch 5", "build 30", "test 20", "package 10"].iter().map(|l| l.to_string()).collect();
+        let graph = TaskGraph::new(group_by_prerecs(steps), parse_durations(&lines).unwrap()).unwrap();
+        assert_eq!(order(&graph), vec!["fetch", "build", "package", "test"]);
+        let schedule = multiple_worker_order(&graph, 2);
+        assert_eq!(schedule.order, vec!["fetch", "build", "package", "test"]);
+        assert_eq!(schedule.total, 55);
+        assert_eq!(parse_durations(&["fetch soon".to_string()]).unwrap_err().to_string(),
+                   "expected a step and its seconds, found \"fetch soon\"");
+        assert_eq!(puzzle_graph(entries(&[("1","A")]), 0).unwrap_err(), GraphError::MissingDuration("1".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_graphs() {
+        let prerecs = group_by_prerecs(entries(&[("A","B"), ("B","C"), ("C","D"), ("D","B")]));
+        let durations : HashMap<String, u32> = prerecs.keys().map(|k| (k.clone(), 1)).collect();
+        let err = TaskGraph::new(prerecs, durations.clone()).unwrap_err();
+        assert_eq!(err, GraphError::Cycle(vec!["B".to_string(), "C".to_string(), "D".to_string()]));
+        assert_eq!(err.to_string(), "steps form a cycle: B -> C -> D -> B");
+        assert_eq!(puzzle_graph(entries(&[("A","B"), ("B","A")]), 0).unwrap_err().to_string(), "steps form a cycle: A -> B -> A");
+
+        let mut prerecs = group_by_prerecs(entries(&[("A","B")]));
+        prerecs.get_mut("B").unwrap().push("Z".to_string());
+        assert_eq!(TaskGraph::new(prerecs, durations.clone()).unwrap_err().to_string(), "step B requires unknown step Z");
+
+        let prerecs = group_by_prerecs(entries(&[("A","E")]));
+        assert_eq!(TaskGraph::new(prerecs, durations).unwrap_err(), GraphError::MissingDuration("E".to_string()));
+    }
+
+    #[test]
+    fn test_repeated_prerequisite() {
+        let graph = puzzle_graph(entries(&[("A","B"), ("A","B"), ("B","C")]), 0).unwrap();
+        assert_eq!(graph.prerecs["B"], vec!["A"]);
+        let schedule = multiple_worker_order(&graph, 2);
+        assert_eq!(schedule.order, vec!["A", "B", "C"]);
+        assert_eq!(schedule.total, 6);
+        assert_eq!(workers_for_critical_path(&graph), 1);
+
+        // built without the checks in TaskGraph::new, B and C wait on each other forever
+        let prerecs = group_by_prerecs(entries(&[("B","C"), ("C","B"), ("A","D")]));
+        let durations = prerecs.keys().map(|k| (k.clone(), 1)).collect();
+        let graph = TaskGraph { prerecs, durations, kinds : HashMap::new() };
+        assert_eq!(simulate(&graph, &[Worker::new()], &Alphabetical).unwrap_err(),
+                   GraphError::Unscheduled(vec!["B".to_string(), "C".to_string()]));
+    }
+
+    #[test]
+    fn test_workers_for_critical_path_any_policy() {
+        // Z and Y make the critical path, but A and B come first alphabetically
+        let prerecs : HashMap<String, Vec<String>> = vec![("A", vec![]), ("B", vec![]), ("Y", vec!["Z"]), ("Z", vec![])]
+            .into_iter().map(|(k, v)| (k.to_string(), v.into_iter().map(String::from).collect())).collect();
+        let durations = prerecs.keys().map(|k| (k.clone(), 2)).collect();
+        let graph = TaskGraph::new(prerecs, durations).unwrap();
+        assert_eq!(analyse(&graph).length, 4);
+        assert_eq!(multiple_worker_order(&graph, 2).total, 6);
+        assert_eq!(multiple_worker_order(&graph, 3).total, 4);
+        assert_eq!(simulate(&graph, &[Worker::new(), Worker::new()], &CriticalPathFirst::new(&graph)).unwrap().total, 4);
+        assert_eq!(workers_for_critical_path(&graph), 2);
+    }
+
+    #[test]
+    fn test_steps_finishing_together() {
+        // P and Q both finish at second 1, so A, B and D are all available then
+        let prerecs = group_by_prerecs(entries(&[("P","D"), ("Q","A"), ("Q","B")]));
+        let durations = prerecs.keys().map(|k| (k.clone(), if k == "A" || k == "B" { 5 } else { 1 })).collect();
+        let graph = TaskGraph::new(prerecs, durations).unwrap();
+        let schedule = multiple_worker_order(&graph, 2);
+        let started : Vec<(&str, u32)> = schedule.assignments.iter().map(|a| (a.step.as_str(), a.start)).collect();
+        assert_eq!(started, vec![("P", 0), ("Q", 0), ("A", 1), ("B", 1), ("D", 6)]);
+        assert_eq!(schedule.order, vec!["P", "Q", "A", "B", "D"]);
+        assert_eq!(schedule.total, 7);
+    }
+}
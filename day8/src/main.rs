@@ -1,11 +1,32 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
+use std::io::BufReader;
 
 fn main() {
-    let input = read_contents_of_file("input.txt");
-    println!("Pt1 {}", sum_of_metadata(&input));
-    println!("Pt2 {}", value_of_rootnode(&input));
-       
+    let args : Vec<String> = env::args().collect();
+    let f = File::open("input.txt").expect("file not found");
+    match parse_tree(BufReader::new(f)) {
+        Ok(root) => match args.get(1).map(|a| a.as_str()) {
+            Some("tree") => print!("{}", root.pretty_print()),
+            Some("serialise") => println!("{}", root.serialise()),
+            Some("stats") => {
+                let fan_out = root.max_fan_out();
+                println!("Depth {}", root.depth());
+                println!("Nodes {}", root.node_count());
+                println!("Max fan-out {} at {:?}", fan_out, root.path_to(|n| n.children.len() == fan_out).unwrap());
+                println!("Value with 0-based indices {}", root.indexed_value(0));
+            },
+            _ => {
+                println!("Pt1 {}", root.sum_metadata());
+                println!("Pt2 {}", root.value_of_node());
+            }
+        },
+        Err(e) => println!("Invalid license file: {}", e)
+    }
 }
 
 struct Node {
@@ -14,69 +35,257 @@ struct Node {
 }
 
 impl Node {
-    fn sum_metadata(&self) -> usize {
-        let mut sum = self.metadata.iter().fold(0, |acc, m| acc + *m);
-        for child in &self.children {
-            sum = sum + child.sum_metadata()
+    // combines the results for each node's children into one for the node,
+    // working up from the leaves. An explicit stack is used so deep trees
+    // can't overflow the call stack.
+    fn fold<T, F : FnMut(&Node, Vec<T>) -> T>(&self, mut combine : F) -> T {
+        // each node being folded alongside the results of its finished children
+        let mut stack : Vec<(&Node, Vec<T>)> = vec![(self, vec![])];
+        loop {
+            let (node, finished) = {
+                let top = stack.last().unwrap();
+                (top.0, top.1.len())
+            };
+            if finished < node.children.len() {
+                stack.push((&node.children[finished], vec![]));
+                continue;
+            }
+            let (node, results) = stack.pop().unwrap();
+            let result = combine(node, results);
+            match stack.last_mut() {
+                Some((_, siblings)) => siblings.push(result),
+                None => return result
+            }
         }
-        sum
+    }
+
+    // the child indices leading to the first node, in depth first order,
+    // which matches the predicate
+    fn path_to<P : Fn(&Node) -> bool>(&self, predicate : P) -> Option<Vec<usize>> {
+        if predicate(self) {
+            return Some(vec![]);
+        }
+        // the nodes on the current path alongside the next of their children to visit,
+        // and the child indices leading to the last of them
+        let mut stack : Vec<(&Node, usize)> = vec![(self, 0)];
+        let mut path = vec![];
+        while let Some(&(node, next)) = stack.last() {
+            match node.children.get(next) {
+                Some(child) => {
+                    stack.last_mut().unwrap().1 += 1;
+                    path.push(next);
+                    if predicate(child) {
+                        return Some(path);
+                    }
+                    stack.push((child, 0));
+                },
+                None => {
+                    stack.pop();
+                    path.pop();
+                }
+            }
+        }
+        None
+    }
+
+    fn sum_metadata(&self) -> usize {
+        self.fold(|node, children : Vec<usize>| node.metadata.iter().sum::<usize>() + children.iter().sum::<usize>())
     }
 
     fn value_of_node(&self) -> usize {
-        let mut value = 0;
-        if self.children.len() == 0 {
-            value = self.metadata.iter().fold(0, |acc, m| acc + *m);
+        self.indexed_value(1)
+    }
+
+    // the value of the node where metadata entries refer to children counting
+    // from first_index
+    fn indexed_value(&self, first_index : usize) -> usize {
+        self.fold(|node, children : Vec<usize>| node.value_from(&children, first_index))
+    }
+
+    // the value of this node given the values of its children
+    fn value_from(&self, children : &[usize], first_index : usize) -> usize {
+        if children.is_empty() {
+            self.metadata.iter().sum()
         } else {
-            for i in &self.metadata {
-                let n = (&self.children).len();
-                if i <= &n {
-                    value = value + &self.children[i-1].value_of_node();
-                }
+            self.metadata.iter()
+                         .filter_map(|i| i.checked_sub(first_index).and_then(|i| children.get(i)))
+                         .sum()
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.fold(|_, children : Vec<usize>| 1 + children.into_iter().max().unwrap_or(0))
+    }
+
+    fn node_count(&self) -> usize {
+        self.fold(|_, children : Vec<usize>| 1 + children.iter().sum::<usize>())
+    }
+
+    fn max_fan_out(&self) -> usize {
+        self.fold(|node, children : Vec<usize>| children.into_iter().max().unwrap_or(0).max(node.children.len()))
+    }
+
+    // the space separated numbers parse_tree reads this node back from. The
+    // children still to be written are tracked on a stack, as in parsing.
+    fn serialise(&self) -> String {
+        let mut numbers = vec![self.children.len(), self.metadata.len()];
+        let mut stack = vec![(self, 0)];
+        while let Some((node, next)) = stack.pop() {
+            match node.children.get(next) {
+                Some(child) => {
+                    numbers.push(child.children.len());
+                    numbers.push(child.metadata.len());
+                    stack.push((node, next + 1));
+                    stack.push((child, 0));
+                },
+                None => numbers.extend(&node.metadata)
             }
         }
-        value
+        numbers.iter().map(|n| n.to_string()).collect::<Vec<String>>().join(" ")
     }
 
+    // an indented outline of the tree with the metadata, metadata sum and
+    // value of every node
+    fn pretty_print(&self) -> String {
+        // the sum and value of every node from a single fold, keyed by address
+        let mut totals = HashMap::<*const Node, (usize, usize)>::new();
+        self.fold(|node, children : Vec<(usize, usize)>| {
+            let sum = node.metadata.iter().sum::<usize>() + children.iter().map(|c| c.0).sum::<usize>();
+            let values : Vec<usize> = children.iter().map(|c| c.1).collect();
+            let total = (sum, node.value_from(&values, 1));
+            totals.insert(node, total);
+            total
+        });
+        let mut out = String::new();
+        let mut stack = vec![(self, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            let metadata : Vec<String> = node.metadata.iter().map(|m| m.to_string()).collect();
+            let (sum, value) = totals[&(node as *const Node)];
+            out.push_str(&format!("{}metadata [{}] sum {} value {}\n", "  ".repeat(depth), metadata.join(" "), sum, value));
+            stack.extend(node.children.iter().rev().map(|child| (child, depth + 1)));
+        }
+        out
+    }
+}
+
+// dropping a deep tree recursively would overflow the stack, so the children
+// are flattened onto a list and dropped one at a time instead
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut pending = std::mem::take(&mut self.children);
+        while let Some(mut node) = pending.pop() {
+            pending.append(&mut node.children);
+        }
+    }
 }
 
-fn read_contents_of_file(filename : &str) -> String {
-    let mut f = File::open(filename).expect("file not found");
-    let mut contents = String::new();
-    f.read_to_string(&mut contents);
-    contents
+#[derive(Debug)]
+enum ParseError {
+    Io(io::Error),
+    NotANumber { offset : usize, token : String },
+    // the offsets are those of the first number of the node's header
+    MissingHeader { offset : usize },
+    MissingMetadataCount { offset : usize },
+    MissingChildren { offset : usize, declared : usize, found : usize },
+    MissingMetadata { offset : usize, declared : usize, found : usize },
+    TrailingInput { offset : usize }
 }
 
-fn parse_node(input : &mut std::slice::Iter<usize>) -> Node {
-    let child_count = input.next().unwrap();
-    let metadata_count = input.next().unwrap();
-    let mut children = vec![];
-    let mut metadata = vec![];
-    for _ in 0..*child_count {
-        children.push(parse_node(input));
-    };
-    for _ in 0..*metadata_count {
-        metadata.push(*input.next().unwrap())
-    };
-    Node {
-        children: children,
-        metadata: metadata
+impl fmt::Display for ParseError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "unable to read input: {}", e),
+            ParseError::NotANumber { offset, token } => write!(f, "expected a number at offset {} but found {:?}", offset, token),
+            ParseError::MissingHeader { offset } => write!(f, "expected a node at offset {} but input ended", offset),
+            ParseError::MissingMetadataCount { offset } => write!(f, "node at offset {} has no metadata count", offset),
+            ParseError::MissingChildren { offset, declared, found } =>
+                write!(f, "node at offset {} declares {} children but input ended after {}", offset, declared, found),
+            ParseError::MissingMetadata { offset, declared, found } =>
+                write!(f, "node at offset {} declares {} metadata entries but input ended after {}", offset, declared, found),
+            ParseError::TrailingInput { offset } => write!(f, "unexpected number at offset {} after the root node", offset)
+        }
     }
 }
 
-fn parse_input(input : &str) -> Vec<usize> {
-    input.to_owned().trim().split(' ').map(|s| s.parse::<usize>().unwrap()).collect()
+// reads whitespace separated numbers from a stream, tracking how many have
+// been read so errors can say where they happened
+struct Numbers<R : BufRead> {
+    bytes : io::Bytes<R>,
+    offset : usize
 }
 
-fn sum_of_metadata(input : &str) -> usize {
-    let values = parse_input(input);
-    let mut it = values.iter();
-    parse_node(&mut it).sum_metadata()
+impl<R : BufRead> Numbers<R> {
+    fn new(reader : R) -> Numbers<R> {
+        Numbers { bytes : reader.bytes(), offset : 0 }
+    }
+
+    // the next number, or None once the input has ended
+    fn next(&mut self) -> Result<Option<usize>, ParseError> {
+        let mut token = String::new();
+        for b in &mut self.bytes {
+            let c = b.map_err(ParseError::Io)? as char;
+            if !c.is_whitespace() {
+                token.push(c);
+            } else if !token.is_empty() {
+                break;
+            }
+        }
+        if token.is_empty() {
+            return Ok(None);
+        }
+        let offset = self.offset;
+        self.offset += 1;
+        token.parse::<usize>().map(Some).map_err(|_| ParseError::NotANumber { offset, token })
+    }
 }
 
-fn value_of_rootnode(input : &str) -> usize {
-    let values = parse_input(input);
-    let mut it = values.iter();
-    parse_node(&mut it).value_of_node()
+// a node whose children are still being read
+struct Partial {
+    offset : usize,
+    child_count : usize,
+    metadata_count : usize,
+    children : Vec<Node>
+}
+
+// parses the tree keeping the nodes still being read on an explicit stack, so
+// deep trees can't overflow the call stack
+fn parse_tree<R : BufRead>(reader : R) -> Result<Node, ParseError> {
+    let mut input = Numbers::new(reader);
+    let mut stack : Vec<Partial> = vec![];
+    loop {
+        // read the header of the next node
+        let offset = input.offset;
+        let child_count = match input.next()? {
+            Some(n) => n,
+            None => return Err(match stack.last() {
+                Some(parent) => ParseError::MissingChildren { offset : parent.offset, declared : parent.child_count, found : parent.children.len() },
+                None => ParseError::MissingHeader { offset }
+            })
+        };
+        let metadata_count = input.next()?.ok_or(ParseError::MissingMetadataCount { offset })?;
+        stack.push(Partial { offset, child_count, metadata_count, children : vec![] });
+
+        // finish off every node which has all of its children
+        while stack.last().is_some_and(|p| p.children.len() == p.child_count) {
+            let partial = stack.pop().unwrap();
+            let mut metadata = vec![];
+            for found in 0..partial.metadata_count {
+                let entry = input.next()?.ok_or(ParseError::MissingMetadata { offset : partial.offset, declared : partial.metadata_count, found })?;
+                metadata.push(entry);
+            }
+            let node = Node { children : partial.children, metadata };
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => {
+                    let offset = input.offset;
+                    return match input.next()? {
+                        Some(_) => Err(ParseError::TrailingInput { offset }),
+                        None => Ok(node)
+                    };
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -86,12 +295,98 @@ mod test {
     #[test]
     fn test_sum_of_metadata() {
         let input = "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2";
-        assert_eq!(sum_of_metadata(input), 138);
+        assert_eq!(parse_tree(input.as_bytes()).unwrap().sum_metadata(), 138);
     }
 
     #[test]
     fn test_value_of_root_node() {
         let input = "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2";
-        assert_eq!(value_of_rootnode(input), 66);
+        assert_eq!(parse_tree(input.as_bytes()).unwrap().value_of_node(), 66);
+    }
+
+    #[test]
+    fn test_fold_queries() {
+        let input = "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2";
+        let root = parse_tree(input.as_bytes()).unwrap();
+        assert_eq!(root.depth(), 3);
+        assert_eq!(root.node_count(), 4);
+        assert_eq!(root.max_fan_out(), 2);
+        assert_eq!(root.path_to(|n| n.metadata == vec![99]), Some(vec![1, 0]));
+        assert_eq!(root.path_to(|n| n.metadata.is_empty()), None);
+        // with 0-based indices the metadata 1 1 2 refers to B, B and nothing
+        assert_eq!(root.indexed_value(0), 0);
+        let max_metadata = root.fold(|n, children : Vec<usize>| n.metadata.iter().chain(children.iter()).cloned().max().unwrap_or(0));
+        assert_eq!(max_metadata, 99);
+    }
+
+    #[test]
+    fn test_serialise() {
+        let input = "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2";
+        assert_eq!(parse_tree(input.as_bytes()).unwrap().serialise(), input);
+        let leaf = |metadata : Vec<usize>| Node { children : vec![], metadata };
+        let tree = Node { children : vec![leaf(vec![3]), Node { children : vec![leaf(vec![])], metadata : vec![1, 1] }], metadata : vec![2] };
+        assert_eq!(tree.serialise(), "2 1 0 1 3 1 2 0 0 1 1 2");
+        assert_eq!(parse_tree(tree.serialise().as_bytes()).unwrap().serialise(), tree.serialise());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_pretty_print() {
+        let input = "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2";
+        assert_eq!(parse_tree(input.as_bytes()).unwrap().pretty_print(), "\
+metadata [1 1 2] sum 138 value 66
+  metadata [10 11 12] sum 33 value 33
+  metadata [2] sum 101 value 0
+    metadata [99] sum 99 value 99
+");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |input : &str| parse_tree(input.as_bytes()).err().unwrap().to_string();
+        assert_eq!(error(""), "expected a node at offset 0 but input ended");
+        assert_eq!(error("2"), "node at offset 0 has no metadata count");
+        assert_eq!(error("3 1 0 1 5"), "node at offset 0 declares 3 children but input ended after 1");
+        assert_eq!(error("2 3 0 3 10 11 12 1 1 0 1 99 2 1"), "node at offset 0 declares 3 metadata entries but input ended after 1");
+        assert_eq!(error("1 1 0 2 7"), "node at offset 2 declares 2 metadata entries but input ended after 1");
+        assert_eq!(error("0 1 5 6"), "unexpected number at offset 3 after the root node");
+        assert_eq!(error("0 1 x"), "expected a number at offset 2 but found \"x\"");
+    }
+
+    #[test]
+    fn test_parse_multiline() {
+        let input = "2 3 0 3 10 11 12\n1 1 0 1 99 2\n1 1 2\n";
+        assert_eq!(parse_tree(input.as_bytes()).unwrap().sum_metadata(), 138);
+    }
+
+    #[test]
+    fn test_parse_deep_tree() {
+        let depth = 100000;
+        let input = format!("{}0 1 1{}", "1 1 ".repeat(depth), " 1".repeat(depth));
+        let root = parse_tree(input.as_bytes()).unwrap();
+        let mut node = &root;
+        let mut levels = 0;
+        while let Some(child) = node.children.first() {
+            node = child;
+            levels += 1;
+        }
+        assert_eq!(levels, depth);
+        assert_eq!(root.depth(), depth + 1);
+        assert_eq!(root.sum_metadata(), depth + 1);
+        assert_eq!(root.serialise(), input);
+        assert_eq!(root.path_to(|n| n.children.is_empty()).map(|p| p.len()), Some(depth));
+    }
+
+    #[test]
+    fn test_pretty_print_deep_tree() {
+        // the outline grows with the square of the depth, so a shallower tree on a
+        // small stack shows printing doesn't recurse
+        let depth = 5000;
+        let input = format!("{}0 1 1{}", "1 1 ".repeat(depth), " 1".repeat(depth));
+        let outline = std::thread::Builder::new().stack_size(64 * 1024).spawn(move || {
+            parse_tree(input.as_bytes()).unwrap().pretty_print()
+        }).unwrap().join().unwrap();
+        assert_eq!(outline.lines().count(), depth + 1);
+        assert_eq!(outline.lines().next(), Some("metadata [1] sum 5001 value 1"));
+        assert_eq!(outline.lines().last().unwrap().trim_start(), "metadata [1] sum 1 value 1");
+    }
+}
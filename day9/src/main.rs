@@ -1,72 +1,350 @@
 use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
 
+// usage: day9 [input file, or - for stdin] [--multiplier N] [--replay ROUNDS] [--round ROUND] [--scoring N]
 fn main() {
-    println!("Pt1 {}", play_marble_game(479,71035));
-    println!("Pt2 {}", play_marble_game(479,7103500));
+    let args : Vec<String> = env::args().skip(1).collect();
+    let mut filename = "input".to_string();
+    let mut multiplier = 100;
+    let mut replay = None;
+    let mut round = None;
+    let mut scoring = None;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--multiplier" => multiplier = it.next().and_then(|m| m.parse().ok()).expect("--multiplier needs a number"),
+            "--replay" => replay = Some(it.next().and_then(|m| m.parse().ok()).expect("--replay needs a number of rounds")),
+            "--round" => round = Some(it.next().and_then(|m| m.parse().ok()).expect("--round needs a round")),
+            "--scoring" => scoring = Some(it.next().and_then(|m| m.parse().ok()).expect("--scoring needs a number")),
+            _ => filename = arg.clone()
+        }
+    }
+    let result = match (replay, round, scoring) {
+        (Some(rounds), _, _) => show_rounds(&filename, 0, rounds),
+        (None, Some(round), _) => show_rounds(&filename, round, round),
+        (None, None, Some(multiple)) => show_variant(&filename, multiple),
+        (None, None, None) => run(&filename, multiplier)
+    };
+    if let Err(e) = result {
+        println!("{}", e);
+    }
+}
+
+// prints the board after each of the rounds from first to last, as in the puzzle
+fn show_rounds(filename : &str, first : u32, last : u32) -> Result<(), GameError> {
+    let (players, _) = parse_game(&read_input(filename)?)?;
+    let mut replay = Replay::new(players, MarbleGame::new());
+    replay.advance_to(first)?;
+    println!("{}", replay.state());
+    while replay.round() < last {
+        replay.step()?;
+        println!("{}", replay.state());
+    }
+    Ok(())
+}
+
+fn run(filename : &str, multiplier : u32) -> Result<(), GameError> {
+    let (players, last_marble) = parse_game(&read_input(filename)?)?;
+    println!("Pt1 {}", play_marble_game(players, last_marble)?);
+    println!("Pt2 {}", play_marble_game(players, scale_last_marble(last_marble, multiplier)?)?);
+    Ok(())
+}
+
+// plays the puzzle game with a different marble scoring, and who won it how
+fn show_variant(filename : &str, scoring_multiple : u32) -> Result<(), GameError> {
+    let (players, last_marble) = parse_game(&read_input(filename)?)?;
+    let rules = Rules { scoring_multiple, ..Rules::standard() };
+    let result = play_game(players, last_marble, MarbleGame::with_rules(rules)?, true)?;
+    println!("Scoring every multiple of {} {}", scoring_multiple, result.high_score());
+    let timeline = result.timeline.as_ref().unwrap();
+    let winner = (0..timeline.len()).max_by_key(|p| result.scores[*p]).unwrap();
+    println!("Winner {} scored {} times", winner + 1, timeline[winner].len());
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum GameError {
+    Io(String),
+    InvalidInput(String),
+    InvalidRules(String),
+    MarbleOverflow { last_marble : u32, multiplier : u32 },
+    ScoreOverflow { player : usize, marble : u32 }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::Io(e) => write!(f, "unable to read input: {}", e),
+            GameError::InvalidInput(e) => write!(f, "invalid input: {}", e),
+            GameError::InvalidRules(e) => write!(f, "invalid rules: {}", e),
+            GameError::MarbleOverflow { last_marble, multiplier } =>
+                write!(f, "last marble {} multiplied by {} is too large", last_marble, multiplier),
+            GameError::ScoreOverflow { player, marble } =>
+                write!(f, "score of player {} overflowed on marble {}", player + 1, marble)
+        }
+    }
+}
+
+fn read_input(filename : &str) -> Result<String, GameError> {
+    let mut contents = String::new();
+    let read = if filename == "-" {
+        io::stdin().read_to_string(&mut contents)
+    } else {
+        File::open(filename).and_then(|mut f| f.read_to_string(&mut contents))
+    };
+    read.map_err(|e| GameError::Io(e.to_string()))?;
+    Ok(contents)
+}
+
+// reads the puzzle sentence "N players; last marble is worth M points"
+fn parse_game(s : &str) -> Result<(usize, u32), GameError> {
+    let words : Vec<&str> = s.trim().trim_end_matches('.').split_whitespace().collect();
+    let invalid = || GameError::InvalidInput(format!("expected \"N players; last marble is worth M points\" but found {:?}", s.trim()));
+    match words.as_slice() {
+        [players, "players;", "last", "marble", "is", "worth", points, "points"] => {
+            let players = players.parse::<usize>().map_err(|_| invalid())?;
+            let points = points.parse::<u32>().map_err(|_| invalid())?;
+            if players == 0 {
+                return Err(GameError::InvalidInput("the game needs at least one player".to_string()));
+            }
+            Ok((players, points))
+        },
+        _ => Err(invalid())
+    }
+}
+
+fn scale_last_marble(last_marble : u32, multiplier : u32) -> Result<u32, GameError> {
+    last_marble.checked_mul(multiplier).ok_or(GameError::MarbleOverflow { last_marble, multiplier })
+}
+
+// the parameters of the game, which the puzzle fixes at 23, 7 and 1 with
+// a single marble 0 to start with
+#[derive(Debug)]
+struct Rules {
+    // marbles which are a multiple of this score rather than being placed,
+    // with 0 meaning no marble ever scores
+    scoring_multiple : u32,
+    // how many marbles anticlockwise of the current one is removed when scoring
+    removal_offset : u32,
+    // how many marbles clockwise of the current one a new marble goes after
+    insert_offset : u32,
+    // the marbles on the board before the first turn, in clockwise order
+    // starting with the current marble
+    initial_layout : Vec<u32>
+}
+
+impl Rules {
+    fn standard() -> Rules {
+        Rules { scoring_multiple : 23, removal_offset : 7, insert_offset : 1, initial_layout : vec![0] }
+    }
 }
 
 struct MarbleGame {
-    board: VecDeque::<u32>
+    board: VecDeque::<u32>,
+    rules : Rules
 }
 
 impl MarbleGame {
     fn new() -> MarbleGame {
+        MarbleGame::with_rules(Rules::standard()).expect("the standard rules are valid")
+    }
+
+    // the front of the board is the current marble, with the marbles behind it
+    // running anticlockwise. Rules which would ever take the last marble off the
+    // board are rejected, as there'd be no current marble left.
+    fn with_rules(rules : Rules) -> Result<MarbleGame, GameError> {
+        let first = match rules.initial_layout.iter().max() {
+            Some(highest) => highest + 1,
+            None => return Err(GameError::InvalidRules("the game needs a marble to start with".to_string()))
+        };
+        // every marble scoring shrinks the board each turn, otherwise at least one marble is
+        // placed between scores, so only scoring straight away from a single marble empties it
+        let multiple = rules.scoring_multiple;
+        if multiple == 1 || (multiple > 1 && rules.initial_layout.len() == 1 && first % multiple == 0) {
+            return Err(GameError::InvalidRules(format!("scoring every multiple of {} empties the board", multiple)));
+        }
         let mut m = MarbleGame {
-            board : VecDeque::<u32>::new()
+            board : VecDeque::<u32>::new(),
+            rules
         };
-        m.insert_marble(0);
-        m
+        for marble in m.rules.initial_layout.clone().into_iter().skip(1) {
+            m.insert_marble(marble);
+        }
+        m.insert_marble(m.rules.initial_layout[0]);
+        Ok(m)
+    }
+
+    // the first marble to be played, following on from the highest already placed
+    fn first_marble(&self) -> u32 {
+        self.rules.initial_layout.iter().max().unwrap() + 1
     }
     
     fn rotate_clockwise(&mut self, places : u32) {
         for _ in 0..places {
-            let v = self.board.pop_back().unwrap();
-            self.board.push_front(v);
+            if let Some(v) = self.board.pop_back() {
+                self.board.push_front(v);
+            }
         }
     }
 
     fn rotate_anti_clockwise(&mut self, places : u32){
         for _ in 0..places {
-            let v = self.board.pop_front().unwrap();
-            self.board.push_back(v);
+            if let Some(v) = self.board.pop_front() {
+                self.board.push_back(v);
+            }
         }
     }
     
     fn remove_marble(&mut self) -> u32 {
-        self.board.pop_front().unwrap()
+        self.board.pop_front().expect("with_rules keeps a marble on the board")
     }
 
     fn insert_marble(&mut self, marble : u32)  {
         self.board.push_front(marble)
     }
     
-    fn place_marble(&mut self, marble : u32 ) -> u32 {
-        if marble % 23 == 0 {
-            self.rotate_anti_clockwise(7);
-            let score = marble + self.remove_marble();
+    fn current(&self) -> u32 {
+        *self.board.front().expect("with_rules keeps a marble on the board")
+    }
+
+    // the marbles in clockwise order, starting from the lowest (usually 0)
+    fn circle(&self) -> Vec<u32> {
+        let mut clockwise : Vec<u32> = self.board.iter().take(1).chain(self.board.iter().skip(1).rev()).cloned().collect();
+        let lowest = (0..clockwise.len()).min_by_key(|i| clockwise[*i]).unwrap();
+        clockwise.rotate_left(lowest);
+        clockwise
+    }
+
+    fn place_marble(&mut self, marble : u32 ) -> u64 {
+        if marble.checked_rem(self.rules.scoring_multiple) == Some(0) {
+            self.rotate_anti_clockwise(self.rules.removal_offset);
+            let score = marble as u64 + self.remove_marble() as u64;
             self.rotate_clockwise(1);
             score
         } else {
-            self.rotate_clockwise(1);
+            self.rotate_clockwise(self.rules.insert_offset);
             self.insert_marble(marble);
             0
         }
     }
 }
 
-fn play_marble_game( num_players : usize, rounds : u32 ) -> u32 {
-    let mut marble_game = MarbleGame::new();
-    let mut score_per_player : Vec<u32> = vec!(0;num_players);
-    let mut player = 0;
-    (1..rounds+1).into_iter().for_each(|round| {
-        score_per_player[player] = score_per_player[player] + marble_game.place_marble(round);
-        player = (player + 1) % num_players
-    });
-    *score_per_player.iter().max().unwrap()
+struct GameResult {
+    scores : Vec<u64>,
+    // for each player the rounds they scored in, with their total after each one
+    timeline : Option<Vec<Vec<(u32, u64)>>>
+}
+
+impl GameResult {
+    fn high_score(&self) -> u64 {
+        *self.scores.iter().max().unwrap()
+    }
+}
+
+fn play_marble_game( num_players : usize, rounds : u32 ) -> Result<u64, GameError> {
+    play_game(num_players, rounds, MarbleGame::new(), false).map(|result| result.high_score())
+}
+
+fn add_score(total : u64, score : u64, player : usize, marble : u32) -> Result<u64, GameError> {
+    total.checked_add(score).ok_or(GameError::ScoreOverflow { player, marble })
+}
+
+// one player placing one marble
+#[derive(Debug, PartialEq)]
+struct Turn {
+    player : usize,
+    marble : u32,
+    score : u64
+}
+
+// a game played a round at a time, so the board can be inspected in between
+struct Replay {
+    game : MarbleGame,
+    scores : Vec<u64>,
+    next_marble : u32,
+    rounds : u32
+}
+
+impl Replay {
+    fn new(num_players : usize, game : MarbleGame) -> Replay {
+        let next_marble = game.first_marble();
+        Replay { game, scores : vec![0; num_players], next_marble, rounds : 0 }
+    }
+
+    // the number of marbles played so far
+    fn round(&self) -> u32 {
+        self.rounds
+    }
+
+    fn step(&mut self) -> Result<Turn, GameError> {
+        let player = self.rounds as usize % self.scores.len();
+        let marble = self.next_marble;
+        let score = self.game.place_marble(marble);
+        self.scores[player] = add_score(self.scores[player], score, player, marble)?;
+        self.next_marble += 1;
+        self.rounds += 1;
+        Ok(Turn { player, marble, score })
+    }
+
+    fn advance_to(&mut self, round : u32) -> Result<(), GameError> {
+        while self.rounds < round {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    // the board as the puzzle shows it, with the player who placed the last
+    // marble followed by the circle from marble 0 and the current marble in brackets
+    fn state(&self) -> String {
+        let mut line = if self.rounds == 0 {
+            "[-]".to_string()
+        } else {
+            format!("[{}]", (self.rounds as usize - 1) % self.scores.len() + 1)
+        };
+        let current = self.game.current();
+        let mut after_current = false;
+        for marble in self.game.circle() {
+            let mut cell = if marble == current { format!("{:>3}", format!("({}", marble)) } else { format!("{:>3}", marble) };
+            if after_current {
+                if cell.starts_with(' ') {
+                    cell.replace_range(..1, ")");
+                } else {
+                    cell.insert(0, ')');
+                }
+            }
+            after_current = marble == current;
+            line.push_str(&cell);
+        }
+        if after_current {
+            line.push(')');
+        }
+        line
+    }
+}
+
+// plays every marble up to and including last_marble
+fn play_game( num_players : usize, last_marble : u32, marble_game : MarbleGame, record_timeline : bool ) -> Result<GameResult, GameError> {
+    let mut replay = Replay::new(num_players, marble_game);
+    let mut timeline = vec![vec![]; num_players];
+    while replay.next_marble <= last_marble {
+        let turn = replay.step()?;
+        if record_timeline && turn.score > 0 {
+            timeline[turn.player].push((turn.marble, replay.scores[turn.player]));
+        }
+    }
+    Ok(GameResult {
+        scores : replay.scores,
+        timeline : if record_timeline { Some(timeline) } else { None }
+    })
 }
 
 
 #[cfg(test)]
+#[allow(clippy::get_first)]
 mod test {
     use super::*;
 
@@ -111,15 +389,113 @@ mod test {
         assert_eq!(*marble_game.board.get(0).unwrap(), 19);
     }
 
+    #[test]
+    fn check_initial_layout(){
+        let rules = Rules { initial_layout : vec![2, 1, 0], ..Rules::standard() };
+        let mut marble_game = MarbleGame::with_rules(rules).unwrap();
+        assert_eq!(marble_game.first_marble(), 3);
+        marble_game.place_marble(3);
+        // the same board as the standard game after its third marble
+        assert_eq!(marble_game.board, vec![3, 1, 2, 0]);
+    }
+
+    #[test]
+    fn check_custom_rules(){
+        let rules = Rules { scoring_multiple : 5, removal_offset : 2, insert_offset : 0, initial_layout : vec![0] };
+        let mut marble_game = MarbleGame::with_rules(rules).unwrap();
+        for m in 1..5 {
+            marble_game.place_marble(m);
+        }
+        // placing each marble directly clockwise of the current one leaves
+        // 4 0 1 2 3 going clockwise
+        assert_eq!(marble_game.board, vec![4, 3, 2, 1, 0]);
+        assert_eq!(marble_game.place_marble(5), 5 + 2);
+        assert_eq!(marble_game.board, vec![3, 1, 0, 4]);
+
+        let no_scoring = Rules { scoring_multiple : 0, ..Rules::standard() };
+        assert_eq!(play_game(9, 25, MarbleGame::with_rules(no_scoring).unwrap(), false).unwrap().high_score(), 0);
+    }
+
+    #[test]
+    fn check_invalid_rules(){
+        let every_marble = Rules { scoring_multiple : 1, ..Rules::standard() };
+        assert_eq!(MarbleGame::with_rules(every_marble).err(),
+                   Some(GameError::InvalidRules("scoring every multiple of 1 empties the board".to_string())));
+        let empty = Rules { initial_layout : vec![], ..Rules::standard() };
+        assert_eq!(MarbleGame::with_rules(empty).err().unwrap().to_string(), "invalid rules: the game needs a marble to start with");
+        // marble 2 would take away the only marble
+        let scores_first = Rules { scoring_multiple : 2, initial_layout : vec![1], ..Rules::standard() };
+        assert!(MarbleGame::with_rules(scores_first).is_err());
+        // from two marbles the board alternates between three and two
+        let alternating = Rules { scoring_multiple : 2, initial_layout : vec![1, 0], ..Rules::standard() };
+        let result = play_game(3, 1000, MarbleGame::with_rules(alternating).unwrap(), false).unwrap();
+        assert!(result.high_score() > 0);
+        let result = play_game(3, 1000, MarbleGame::with_rules(Rules { scoring_multiple : 2, ..Rules::standard() }).unwrap(), false);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn check_score_timeline(){
+        let result = play_game(9, 50, MarbleGame::new(), true).unwrap();
+        let timeline = result.timeline.unwrap();
+        // marble 23 goes to player 5 and marble 46 to player 1
+        assert_eq!(timeline[4], vec![(23, 32)]);
+        assert_eq!(timeline[0], vec![(46, result.scores[0])]);
+        assert!(timeline[1].is_empty());
+        assert!(play_game(9, 25, MarbleGame::new(), false).unwrap().timeline.is_none());
+    }
+
+    #[test]
+    fn check_replay(){
+        let mut replay = Replay::new(9, MarbleGame::new());
+        let mut states = vec![replay.state()];
+        for _ in 0..4 {
+            replay.step().unwrap();
+            states.push(replay.state());
+        }
+        assert_eq!(states, vec![
+            "[-] (0)",
+            "[1]  0 (1)",
+            "[2]  0 (2) 1",
+            "[3]  0  2  1 (3)",
+            "[4]  0 (4) 2  1  3"
+        ]);
+        replay.advance_to(22).unwrap();
+        assert_eq!(replay.state(), "[4]  0 16  8 17  4 18  9 19  2 20 10 21  5(22)11  1 12  6 13  3 14  7 15");
+        assert_eq!(replay.step(), Ok(Turn { player : 4, marble : 23, score : 32 }));
+        assert_eq!(replay.state(), "[5]  0 16  8 17  4 18(19) 2 20 10 21  5 22 11  1 12  6 13  3 14  7 15");
+        replay.advance_to(25).unwrap();
+        assert_eq!(replay.state(), "[7]  0 16  8 17  4 18 19  2 24 20(25)10 21  5 22 11  1 12  6 13  3 14  7 15");
+        assert_eq!(replay.game.circle()[..4].to_vec(), vec![0, 16, 8, 17]);
+        assert_eq!(replay.game.current(), 25);
+    }
+
     #[test]
     fn check_play_marble_game(){
-        assert_eq!(play_marble_game(9,25), 32);
+        assert_eq!(play_marble_game(9,25), Ok(32));
     }
 
     #[test]
     fn check_play_marble_game_2(){
-        assert_eq!(play_marble_game(10,1618), 8317);
-        assert_eq!(play_marble_game(30,5807), 37305);
-        assert_eq!(play_marble_game(17,1104), 2764);        
+        assert_eq!(play_marble_game(10,1618), Ok(8317));
+        assert_eq!(play_marble_game(30,5807), Ok(37305));
+        assert_eq!(play_marble_game(17,1104), Ok(2764));        
+    }
+
+    #[test]
+    fn check_parse_game(){
+        assert_eq!(parse_game("10 players; last marble is worth 1618 points\n"), Ok((10, 1618)));
+        assert_eq!(parse_game("479 players; last marble is worth 71035 points."), Ok((479, 71035)));
+        assert!(parse_game("10 players; last marble is worth lots of points").is_err());
+        assert_eq!(parse_game("0 players; last marble is worth 5 points"),
+                   Err(GameError::InvalidInput("the game needs at least one player".to_string())));
+    }
+
+    #[test]
+    fn check_overflow(){
+        assert_eq!(scale_last_marble(71035, 100), Ok(7103500));
+        assert_eq!(scale_last_marble(71035, 100000).unwrap_err().to_string(), "last marble 71035 multiplied by 100000 is too large");
+        assert_eq!(add_score(u64::MAX - 1, 1, 0, 23), Ok(u64::MAX));
+        assert_eq!(add_score(u64::MAX, 1, 2, 46), Err(GameError::ScoreOverflow { player : 2, marble : 46 }));
     }
 }